@@ -1,17 +1,29 @@
 use std::borrow::Borrow;
+use std::io::Write;
+use std::process;
+use std::str::FromStr;
+use std::sync::Mutex;
 
-use druid::{im::Vector, Event, EventCtx, FileDialogOptions, HotKey, KeyEvent, Selector, SysMods, Application, ClipboardFormat};
+use anyhow::Context;
+
+use druid::{im::Vector, Event, EventCtx, FileDialogOptions, HotKey, KbKey, KeyEvent, RawMods, Selector, SysMods, Application, ClipboardFormat};
 use once_cell::sync::Lazy;
 
 use crate::widgets::{
     editor_view::EditorView,
     item,
-    text_buffer::{syntax::SYNTAXSET, EditStack},
+    text_buffer::{
+        search::{Matcher, SearchQuery},
+        syntax::SYNTAXSET,
+        Case, EditStack, LineOrder, Sequence, Transform,
+    },
     window::{NPWindow, NPWindowState},
     DialogResult, Item, PaletteBuilder, PaletteResult,
 };
 
 const UICOMMAND_CALLBACK: Selector<UICommandCallback> = Selector::new("nonepad.all.uicommand_callback");
+/// Clipboard format of a block selection, pasted column-wise
+const BLOCK_CLIPBOARD_FORMAT: &str = "application/x-nonepad-block";
 
 #[derive(Clone)]
 enum UICommandCallback {
@@ -22,12 +34,13 @@ enum UICommandCallback {
 struct UICommand {
     pub description: String,
     pub show_in_palette: bool,
-    shortcut: Option<druid::HotKey>,
+    /// One hotkey, or two for a chord like `Ctrl-k Ctrl-d`
+    shortcut: Vec<druid::HotKey>,
     exec: UICommandCallback,
 }
 
 impl UICommand {
-    fn new(description: &str, show_in_palette: bool, shortcut: Option<druid::HotKey>, exec: UICommandCallback) -> Self {
+    fn new(description: &str, show_in_palette: bool, shortcut: Vec<druid::HotKey>, exec: UICommandCallback) -> Self {
         Self {
             description: description.to_owned(),
             show_in_palette,
@@ -37,17 +50,58 @@ impl UICommand {
     }
 
     fn matches(&self, event: &KeyEvent) -> bool {
-        self.shortcut.clone().map(|s| s.matches(event)).unwrap_or(false)
+        self.shortcut.len() == 1 && self.shortcut[0].matches(event)
+    }
+
+    fn matches_chord(&self, prefix: &KeyEvent, event: &KeyEvent) -> bool {
+        self.shortcut.len() == 2 && self.shortcut[0].matches(prefix) && self.shortcut[1].matches(event)
+    }
+
+    fn is_chord_prefix(&self, event: &KeyEvent) -> bool {
+        self.shortcut.len() == 2 && self.shortcut[0].matches(event)
     }
 }
 
 struct UICommandSet {
     commands: Vec<UICommand>,
+    /// First key of a chord waiting for the second one
+    chord_prefix: Mutex<Option<KeyEvent>>,
+}
+
+enum ShortcutMatch<'a> {
+    None,
+    /// The key was consumed as part of a chord
+    Chord,
+    Command(&'a UICommand),
 }
 
 impl UICommandSet {
     pub fn new() -> Self {
-        Self { commands: Vec::new() }
+        Self {
+            commands: Vec::new(),
+            chord_prefix: Mutex::new(None),
+        }
+    }
+
+    fn matching(&self, event: &KeyEvent) -> ShortcutMatch<'_> {
+        if matches!(event.key, KbKey::Control | KbKey::Shift | KbKey::Alt | KbKey::Meta) {
+            return ShortcutMatch::None;
+        }
+        let mut chord_prefix = self.chord_prefix.lock().unwrap();
+        if let Some(prefix) = chord_prefix.take() {
+            return match self.commands.iter().find(|c| c.matches_chord(&prefix, event)) {
+                Some(c) => ShortcutMatch::Command(c),
+                None => ShortcutMatch::Chord,
+            };
+        }
+        if self.commands.iter().any(|c| c.is_chord_prefix(event)) {
+            *chord_prefix = Some(event.clone());
+            return ShortcutMatch::Chord;
+        }
+        match self.commands.iter().find(|c| c.matches(event)) {
+            Some(c) => ShortcutMatch::Command(c),
+            None => ShortcutMatch::None,
+        }
     }
 }
 
@@ -84,15 +138,17 @@ impl UICommandEventHandler<NPWindow, NPWindowState> for CommandSet {
 impl UICommandEventHandler<EditorView, EditStack> for CommandSet {
     fn event(&self, ctx: &mut EventCtx, event: &Event, window: &mut EditorView, editor: &mut EditStack) {
         match event {
-            Event::KeyDown(event) => {
-                for c in &VIEWCOMMANDSET.commands {
-                    if c.matches(event.borrow()) {
-                        if let UICommandCallback::EditView(c) = c.exec {
-                            c(window, ctx, editor);
-                        }
+            Event::KeyDown(event) => match VIEWCOMMANDSET.matching(event.borrow()) {
+                ShortcutMatch::Command(c) => {
+                    if let UICommandCallback::EditView(c) = c.exec {
+                        c(window, ctx, editor);
+                        // the key must not reach the editor, or Enter and the arrows would also edit or move
+                        ctx.set_handled();
                     }
                 }
-            }
+                ShortcutMatch::Chord => ctx.set_handled(),
+                ShortcutMatch::None => (),
+            },
             Event::Command(cmd) if cmd.is(UICOMMAND_CALLBACK) => {
                 if let UICommandCallback::EditView(f) = cmd.get_unchecked(UICOMMAND_CALLBACK) {
                     f(window, ctx, editor);
@@ -109,14 +165,20 @@ fn string_to_hotkey(input: &str) -> Option<HotKey> {
     if t.len() != 2 {
         return None;
     }
-    let mods = match t[0] {
-        "Ctrl" => SysMods::Cmd,
-        "CtrlShift" => SysMods::CmdShift,
-        "CtrlAlt" => SysMods::AltCmd,
-        "Shift" => SysMods::Shift,
-        "CtrlAltShift" => SysMods::AltCmdShift,
-        _ => SysMods::None,
+    let mods: Option<RawMods> = match t[0] {
+        "Ctrl" => SysMods::Cmd.into(),
+        "CtrlShift" => SysMods::CmdShift.into(),
+        "CtrlAlt" => SysMods::AltCmd.into(),
+        "Shift" => SysMods::Shift.into(),
+        "CtrlAltShift" => SysMods::AltCmdShift.into(),
+        "Alt" => RawMods::Alt.into(),
+        "AltShift" => RawMods::AltShift.into(),
+        _ => SysMods::None.into(),
     };
+    if t[1].chars().count() > 1 {
+        // A named key, like `ArrowUp` or `Enter`
+        return KbKey::from_str(t[1]).ok().map(|key| HotKey::new(mods, key));
+    }
     #[cfg(target_os = "macos")]
     return Some(HotKey::new(mods, t[1]));
     #[cfg(not(target_os = "macos"))]
@@ -127,11 +189,64 @@ fn string_to_hotkey(input: &str) -> Option<HotKey> {
     }
 }
 
+/// Parse a shortcut, made of one hotkey or of a chord of two space separated hotkeys
+fn string_to_shortcut(input: &str) -> Vec<HotKey> {
+    input.split(' ').filter_map(string_to_hotkey).collect()
+}
+
+/// The matcher of the line filter commands: a pattern between slashes is a regex, anything else is literal
+fn line_filter_matcher(input: &str) -> anyhow::Result<Matcher> {
+    let query = match input.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+        Some(pattern) => SearchQuery {
+            pattern: pattern.to_owned(),
+            regex: true,
+            ..Default::default()
+        },
+        None => SearchQuery::literal(input),
+    };
+    Matcher::new(&query)
+}
+
+fn transform_selections(view: &mut EditorView, ctx: &mut EventCtx, editor: &mut EditStack, transform: Transform) {
+    if let Err(e) = editor.transform_selections(transform) {
+        view.alert(&e.to_string()).show(ctx);
+    }
+}
+
+/// Open `text` in the untitled buffer of a new window, run by another process of the editor
+fn open_in_new_window(text: String) -> anyhow::Result<()> {
+    let mut child = process::Command::new(std::env::current_exe()?)
+        .arg("-")
+        .stdin(process::Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().context("No input to the new window")?;
+    // the new window reads the text when it starts, without blocking this one
+    std::thread::spawn(move || {
+        if let Err(e) = stdin.write_all(text.as_bytes()) {
+            tracing::error!("Error sending the text to the new window: {}", e);
+        }
+        drop(stdin);
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+fn copy_to_clipboard(editor: &EditStack) {
+    let mut clipboard = Application::global().clipboard();
+    match editor.selected_block_text() {
+        Some(block) => clipboard.put_formats(&[
+            ClipboardFormat::new(BLOCK_CLIPBOARD_FORMAT, block.clone()),
+            ClipboardFormat::from(block),
+        ]),
+        None => clipboard.put_string(editor.selected_text()),
+    }
+}
+
 macro_rules! wincmd {
     ($commandset:ident = { $($command:ident = ($description:literal,$hotkey:literal, $v:expr, $b:expr));+ $(;)? } ) => {
         static $commandset: Lazy<UICommandSet> = Lazy::new(|| {
             let mut v = UICommandSet::new();
-            $(v.commands.push(UICommand::new($description, $v,string_to_hotkey($hotkey), UICommandCallback::Window($b) ));)+
+            $(v.commands.push(UICommand::new($description, $v,string_to_shortcut($hotkey), UICommandCallback::Window($b) ));)+
             v
         });
     };
@@ -142,7 +257,7 @@ macro_rules! viewcmd {
     ($commandset:ident = { $($command:ident = ($description:literal,$hotkey:literal, $v:expr, $b:expr));+ $(;)? } ) => {
         static $commandset: Lazy<UICommandSet> = Lazy::new(|| {
             let mut v = UICommandSet::new();
-            $(v.commands.push(UICommand::new($description, $v,string_to_hotkey($hotkey), UICommandCallback::EditView($b) ));)+
+            $(v.commands.push(UICommand::new($description, $v,string_to_shortcut($hotkey), UICommandCallback::EditView($b) ));)+
             v
         });
     };
@@ -178,7 +293,7 @@ wincmd! {
                 .show(ctx);
             true
         });
-        PALCMD_CHANGE_LANGUAGE = ("Change language mode","CtrlShift-l", true,
+        PALCMD_CHANGE_LANGUAGE = ("Change language mode","CtrlShift-m", true,
         |window, ctx, _data| {
             let languages: Vector<Item> = SYNTAXSET.syntaxes().iter().map(|l| Item::new(&l.name,&format!("File extensions : [{}]",l.file_extensions.join(", ")) )).collect();
             window.palette().items(languages)
@@ -257,6 +372,17 @@ viewcmd! {
             ctx.submit_command(crate::widgets::bottom_panel::SHOW_SEARCH_PANEL.with(editor.main_cursor_selected_text()));
             return true;
         });
+        FIND_IN_FILES = ("Find in files","CtrlShift-f", true,
+        |_, ctx, editor| {
+            let folder = editor
+                .filename
+                .as_ref()
+                .and_then(|f| f.parent().filter(|p| !p.as_os_str().is_empty()).map(|p| p.to_path_buf()))
+                .or_else(|| std::env::current_dir().ok())
+                .unwrap_or_default();
+            ctx.submit_command(crate::widgets::bottom_panel::SHOW_FIND_IN_FILES_PANEL.with((editor.main_cursor_selected_text(), folder.to_string_lossy().to_string())));
+            true
+        });
         DUPLICATE_CURSOR_SELECTION = ("Duplicate cursor","Ctrl-d", false,
         |_, _, editor| {
             editor
@@ -264,30 +390,371 @@ viewcmd! {
                 .duplicate_cursor_from_str(&editor.main_cursor_selected_text());
                 return true;
         });
+        SELECT_ALL_OCCURRENCES = ("Select all occurrences","CtrlShift-l", true,
+        |_, _, editor| {
+            editor
+                .buffer
+                .select_all_occurrences(&editor.main_cursor_selected_text());
+            true
+        });
+        SKIP_OCCURRENCE = ("Skip occurrence","Ctrl-k Ctrl-d", true,
+        |_, _, editor| {
+            editor
+                .buffer
+                .skip_occurrence(&editor.main_cursor_selected_text());
+            true
+        });
+        UNDO_LAST_CARET = ("Undo last caret addition","Ctrl-u", true,
+        |_, _, editor| {
+            editor.buffer.undo_last_caret();
+            true
+        });
+        CARETS_TO_LINE_ENDS = ("Add carets to line ends of selection","AltShift-i", true,
+        |_, _, editor| {
+            editor.buffer.add_carets_to_line_ends();
+            true
+        });
+        ADD_CARET_ABOVE = ("Add caret above","Ctrl-k Ctrl-ArrowUp", true,
+        |_, _, editor| {
+            editor.buffer.duplicate_up();
+            true
+        });
+        ADD_CARET_BELOW = ("Add caret below","Ctrl-k Ctrl-ArrowDown", true,
+        |_, _, editor| {
+            editor.buffer.duplicate_down();
+            true
+        });
         COPY = ("Copy selections to clipboard","Ctrl-c", false,
         |_,_,editor| {
-            Application::global().clipboard().put_string(editor.selected_text());
+            copy_to_clipboard(editor);
             return true;
         });
         CUT = ("Cut selections to clipboard","Ctrl-x", false,
         |_,_,editor| {
-            Application::global().clipboard().put_string(editor.selected_text());
+            copy_to_clipboard(editor);
             editor.delete();
             return true;
         });
         PASTE = ("Paste from clipboard","Ctrl-v", false,
         |_,_,editor| {
             let clipboard = Application::global().clipboard();
-            let supported_types = &[ClipboardFormat::TEXT];
+            let supported_types = &[BLOCK_CLIPBOARD_FORMAT, ClipboardFormat::TEXT];
             let best_available_type = clipboard.preferred_format(supported_types);
             if let Some(format) = best_available_type {
                 let data = clipboard
                     .get_format(format)
                     .expect("I promise not to unwrap in production");
-                editor.insert(String::from_utf8_lossy(&data).as_ref());
+                if format == BLOCK_CLIPBOARD_FORMAT {
+                    editor.insert_block(String::from_utf8_lossy(&data).as_ref());
+                } else {
+                    editor.insert(String::from_utf8_lossy(&data).as_ref());
+                }
             }
             return true;
         });
+        MOVE_LINES_UP = ("Move lines up","Alt-ArrowUp", true,
+        |_,_,editor| {
+            editor.move_lines_up();
+            true
+        });
+        MOVE_LINES_DOWN = ("Move lines down","Alt-ArrowDown", true,
+        |_,_,editor| {
+            editor.move_lines_down();
+            true
+        });
+        DUPLICATE_LINES = ("Duplicate lines","CtrlShift-d", true,
+        |_,_,editor| {
+            editor.duplicate_lines();
+            true
+        });
+        DELETE_LINES = ("Delete lines","CtrlShift-k", true,
+        |_,_,editor| {
+            editor.delete_lines();
+            true
+        });
+        JOIN_LINES = ("Join lines","Ctrl-j", true,
+        |_,_,editor| {
+            editor.join_lines();
+            true
+        });
+        INSERT_LINE_BELOW = ("Insert line below","Ctrl-Enter", true,
+        |_,_,editor| {
+            editor.insert_line_below();
+            true
+        });
+        INSERT_LINE_ABOVE = ("Insert line above","CtrlShift-Enter", true,
+        |_,_,editor| {
+            editor.insert_line_above();
+            true
+        });
+        SORT_LINES = ("Sort lines","", true,
+        |_,_,editor| {
+            editor.sort_lines(LineOrder::Lexicographic);
+            true
+        });
+        SORT_LINES_CASE_INSENSITIVE = ("Sort lines (case insensitive)","", true,
+        |_,_,editor| {
+            editor.sort_lines(LineOrder::CaseInsensitive);
+            true
+        });
+        SORT_LINES_NATURAL = ("Sort lines (natural order)","", true,
+        |_,_,editor| {
+            editor.sort_lines(LineOrder::Natural);
+            true
+        });
+        SORT_LINES_BY_LENGTH = ("Sort lines by length","", true,
+        |_,_,editor| {
+            editor.sort_lines(LineOrder::Length);
+            true
+        });
+        REVERSE_LINES = ("Reverse lines","", true,
+        |_,_,editor| {
+            editor.sort_lines(LineOrder::Reverse);
+            true
+        });
+        UNIQUE_LINES = ("Remove duplicated lines","", true,
+        |_,_,editor| {
+            editor.sort_lines(LineOrder::Unique);
+            true
+        });
+        SHUFFLE_LINES = ("Shuffle lines","", true,
+        |_,_,editor| {
+            editor.sort_lines(LineOrder::Shuffle);
+            true
+        });
+        KEEP_MATCHING_LINES = ("Keep lines matching","", true,
+        |view, ctx, _| {
+            view.palette().title("Keep lines matching (text or /regex/)").on_select(|result, ctx, view, editor| {
+                match line_filter_matcher(&result.name) {
+                    Ok(matcher) => {
+                        editor.filter_lines(&matcher, true);
+                    }
+                    Err(e) => view.alert(&format!("Invalid pattern: {}", e)).show(ctx),
+                }
+            }).show(ctx);
+            true
+        });
+        REMOVE_MATCHING_LINES = ("Remove lines matching","", true,
+        |view, ctx, _| {
+            view.palette().title("Remove lines matching (text or /regex/)").on_select(|result, ctx, view, editor| {
+                match line_filter_matcher(&result.name) {
+                    Ok(matcher) => {
+                        editor.filter_lines(&matcher, false);
+                    }
+                    Err(e) => view.alert(&format!("Invalid pattern: {}", e)).show(ctx),
+                }
+            }).show(ctx);
+            true
+        });
+        COPY_MATCHING_LINES = ("Copy lines matching to a new buffer","", true,
+        |view, ctx, _| {
+            view.palette().title("Copy lines matching (text or /regex/)").on_select(|result, ctx, view, editor| {
+                let matcher = match line_filter_matcher(&result.name) {
+                    Ok(matcher) => matcher,
+                    Err(e) => {
+                        view.alert(&format!("Invalid pattern: {}", e)).show(ctx);
+                        return;
+                    }
+                };
+                if let Err(e) = open_in_new_window(editor.lines_matching(&matcher)) {
+                    view.alert(&format!("Unable to open a new window: {}", e)).show(ctx);
+                }
+            }).show(ctx);
+            true
+        });
+        TOGGLE_LINE_COMMENT = ("Toggle line comment","Ctrl-/", true,
+        |_,_,editor| {
+            editor.toggle_line_comment();
+            true
+        });
+        TOGGLE_BLOCK_COMMENT = ("Toggle block comment","CtrlShift-a", true,
+        |_,_,editor| {
+            editor.toggle_block_comment();
+            true
+        });
+        SURROUND_WITH = ("Surround with...","", true,
+        |view, ctx, _| {
+            view.palette().title("Surround with (bracket, quote or <tag>)").on_select(|result, ctx, view, editor| {
+                if !editor.surround_with(&result.name) {
+                    view.alert(&format!("Can't surround with {}", result.name)).show(ctx);
+                }
+            }).show(ctx);
+            true
+        });
+        CHANGE_SURROUNDING = ("Change surrounding","", true,
+        |view, ctx, _| {
+            view.palette().title("Change surrounding to (bracket, quote or <tag>)").on_select(|result, ctx, view, editor| {
+                if !view.change_surrounding(editor, Some(&result.name)) {
+                    view.alert(&format!("Can't surround with {}", result.name)).show(ctx);
+                }
+            }).show(ctx);
+            true
+        });
+        DELETE_SURROUNDING = ("Delete surrounding","", true,
+        |view,_,editor| {
+            view.change_surrounding(editor, None);
+            true
+        });
+        // Ctrl-Shift-\ gives a | with the shift
+        GOTO_MATCHING_BRACKET = ("Go to matching bracket","CtrlShift-|", true,
+        |view, _, editor| {
+            view.goto_matching_bracket(editor);
+            true
+        });
+        TOGGLE_RAINBOW_BRACKETS = ("Toggle rainbow brackets","", true,
+        |view, ctx, _| {
+            view.toggle_rainbow_brackets();
+            ctx.request_paint();
+            true
+        });
+        RENAME_TAG = ("Rename tag","CtrlShift-e", true,
+        |view, ctx, editor| {
+            if !view.select_tag_pair(editor) {
+                view.alert("No paired tag at the caret").show(ctx);
+            }
+            true
+        });
+        UPPER_CASE = ("Convert to UPPER CASE","", true,
+        |_,_,editor| {
+            editor.convert_case(Case::Upper);
+            true
+        });
+        LOWER_CASE = ("Convert to lower case","", true,
+        |_,_,editor| {
+            editor.convert_case(Case::Lower);
+            true
+        });
+        TITLE_CASE = ("Convert to Title Case","", true,
+        |_,_,editor| {
+            editor.convert_case(Case::Title);
+            true
+        });
+        SNAKE_CASE = ("Convert to snake_case","", true,
+        |_,_,editor| {
+            editor.convert_case(Case::Snake);
+            true
+        });
+        CAMEL_CASE = ("Convert to camelCase","", true,
+        |_,_,editor| {
+            editor.convert_case(Case::Camel);
+            true
+        });
+        PASCAL_CASE = ("Convert to PascalCase","", true,
+        |_,_,editor| {
+            editor.convert_case(Case::Pascal);
+            true
+        });
+        KEBAB_CASE = ("Convert to kebab-case","", true,
+        |_,_,editor| {
+            editor.convert_case(Case::Kebab);
+            true
+        });
+        CONSTANT_CASE = ("Convert to CONSTANT_CASE","", true,
+        |_,_,editor| {
+            editor.convert_case(Case::Constant);
+            true
+        });
+        TOGGLE_CASE = ("Toggle case","", true,
+        |_,_,editor| {
+            editor.convert_case(Case::Toggle);
+            true
+        });
+        BASE64_ENCODE = ("Base64 encode","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::Base64Encode);
+            true
+        });
+        BASE64_DECODE = ("Base64 decode","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::Base64Decode);
+            true
+        });
+        URL_ENCODE = ("URL encode","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::UrlEncode);
+            true
+        });
+        URL_DECODE = ("URL decode","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::UrlDecode);
+            true
+        });
+        HTML_ESCAPE = ("HTML escape","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::HtmlEscape);
+            true
+        });
+        HTML_UNESCAPE = ("HTML unescape","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::HtmlUnescape);
+            true
+        });
+        JSON_ESCAPE = ("JSON string escape","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::JsonEscape);
+            true
+        });
+        JSON_UNESCAPE = ("JSON string unescape","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::JsonUnescape);
+            true
+        });
+        HEX_DUMP = ("Hex dump","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::HexDump);
+            true
+        });
+        SHA256_DIGEST = ("SHA-256 digest","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::Sha256);
+            true
+        });
+        MD5_DIGEST = ("MD5 digest","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::Md5);
+            true
+        });
+        EVALUATE = ("Evaluate expression","", true,
+        |view, ctx, editor| {
+            transform_selections(view, ctx, editor, Transform::Evaluate);
+            true
+        });
+        INSERT_SEQUENCE = ("Insert sequence...","", true,
+        |view, ctx, _| {
+            view.palette().title("Start [step], like 1, 001 5 or 0x0a").on_select(|result, ctx, view, editor| {
+                match Sequence::parse(&result.name) {
+                    Some(sequence) => editor.insert_sequence(&sequence),
+                    None => view.alert(&format!("Invalid sequence {}", result.name)).show(ctx),
+                }
+            }).show(ctx);
+            true
+        });
+        INCREMENT_NUMBER = ("Increment number","CtrlAlt-ArrowUp", true,
+        |_,_,editor| {
+            editor.increment_numbers(1);
+            true
+        });
+        DECREMENT_NUMBER = ("Decrement number","CtrlAlt-ArrowDown", true,
+        |_,_,editor| {
+            editor.increment_numbers(-1);
+            true
+        });
+        ALIGN_CARETS = ("Align carets","", true,
+        |_,_,editor| {
+            editor.align_carets();
+            true
+        });
+        ALIGN_SELECTION_ON = ("Align selection on...","", true,
+        |view, ctx, _| {
+            view.palette().title("Delimiter").on_select(|result, ctx, view, editor| {
+                if result.name.is_empty() {
+                    view.alert("No delimiter").show(ctx);
+                } else if editor.align_on(&result.name) == 0 {
+                    view.alert(&format!("No lines to align on {}", result.name)).show(ctx);
+                }
+            }).show(ctx);
+            true
+        });
         UNDO = ("Undo","Ctrl-z", false,
         |_,_,editor| {
             editor.undo();
@@ -298,6 +765,38 @@ viewcmd! {
             editor.redo();
             return true;
         });
+        UNDO_HISTORY = ("Undo history","", true,
+        |view, ctx, editor| {
+            let now = std::time::SystemTime::now();
+            let states = editor.undo_states();
+            let items: Vector<Item> = states.iter().map(|s| Item::new(&s.label(now), "")).collect();
+            let ids: Vec<usize> = states.iter().map(|s| s.id).collect();
+            view.palette().items(items).title("Go to undo state").on_select(move |result, _ctx, _ev, editor| {
+                if let Some(&id) = ids.get(result.index) {
+                    editor.goto_undo_state(id);
+                }
+            }).show(ctx);
+            true
+        });
+        SET_UNDO_MEMORY_LIMIT = ("Set undo memory limit...","", true,
+        |view, ctx, _| {
+            view.palette().title("Undo memory limit in MB, 64 by default").on_select(|result, ctx, view, editor| {
+                match result.name.trim().parse::<usize>() {
+                    Ok(mb) if mb > 0 => editor.set_undo_memory_limit(mb * 1024 * 1024),
+                    _ => view.alert(&format!("Invalid memory limit {}", result.name)).show(ctx),
+                }
+            }).show(ctx);
+            true
+        });
+        TOGGLE_KEEP_UNDO_HISTORY = ("Toggle keeping the undo history after closing","", true,
+        |view, ctx, editor| {
+            match editor.toggle_keep_history() {
+                Ok(true) => view.show_status(ctx, "The undo history is kept after closing"),
+                Ok(false) => view.show_status(ctx, "The undo history is not kept after closing"),
+                Err(e) => view.alert(&e.to_string()).show(ctx),
+            }
+            true
+        });
         SELECT_ALL = ("Select all text","Ctrl-a", true,
         |_,_,editor| {
             editor.select_all();
@@ -305,3 +804,73 @@ viewcmd! {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use druid::Modifiers;
+
+    use super::*;
+
+    fn command(mods: Modifiers, key: KbKey) -> Option<&'static str> {
+        match VIEWCOMMANDSET.matching(&KeyEvent::for_test(mods, key)) {
+            ShortcutMatch::Command(c) => Some(&c.description),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn language_mode_keeps_a_shortcut() {
+        let event = KeyEvent::for_test(Modifiers::CONTROL | Modifiers::SHIFT, KbKey::Character("M".into()));
+        let language = WINCOMMANDSET.commands.iter().find(|c| c.matches(&event));
+        assert_eq!(language.map(|c| c.description.as_str()), Some("Change language mode"));
+        let event = KeyEvent::for_test(Modifiers::CONTROL | Modifiers::SHIFT, KbKey::Character("L".into()));
+        assert_eq!(command(event.mods, event.key), Some("Select all occurrences"));
+    }
+
+    #[test]
+    fn number_shortcuts_are_dispatched_to_commands() {
+        let ctrl_alt = Modifiers::CONTROL | Modifiers::ALT;
+        assert_eq!(command(ctrl_alt, KbKey::ArrowUp), Some("Increment number"));
+        assert_eq!(command(ctrl_alt, KbKey::ArrowDown), Some("Decrement number"));
+    }
+
+    fn chord_command(mods: Modifiers, key: KbKey) -> Option<&'static str> {
+        let prefix = KeyEvent::for_test(Modifiers::CONTROL, KbKey::Character("k".into()));
+        let event = KeyEvent::for_test(mods, key);
+        VIEWCOMMANDSET
+            .commands
+            .iter()
+            .find(|c| c.matches_chord(&prefix, &event))
+            .map(|c| c.description.as_str())
+    }
+
+    #[test]
+    fn caret_duplication_is_a_chord() {
+        assert_eq!(
+            chord_command(Modifiers::CONTROL, KbKey::ArrowUp),
+            Some("Add caret above")
+        );
+        assert_eq!(
+            chord_command(Modifiers::CONTROL, KbKey::ArrowDown),
+            Some("Add caret below")
+        );
+        // the block selection keeps Alt+Shift+Arrow
+        let alt_shift = Modifiers::ALT | Modifiers::SHIFT;
+        assert_eq!(command(alt_shift, KbKey::ArrowUp), None);
+        assert_eq!(command(alt_shift, KbKey::ArrowDown), None);
+    }
+
+    #[test]
+    fn line_shortcuts_are_dispatched_to_commands() {
+        assert_eq!(command(Modifiers::ALT, KbKey::ArrowUp), Some("Move lines up"));
+        assert_eq!(command(Modifiers::ALT, KbKey::ArrowDown), Some("Move lines down"));
+        assert_eq!(command(Modifiers::CONTROL, KbKey::Enter), Some("Insert line below"));
+        assert_eq!(
+            command(Modifiers::CONTROL | Modifiers::SHIFT, KbKey::Enter),
+            Some("Insert line above")
+        );
+        // plain keys are left to the editor
+        assert_eq!(command(Modifiers::empty(), KbKey::ArrowUp), None);
+        assert_eq!(command(Modifiers::empty(), KbKey::Enter), None);
+    }
+}
@@ -6,6 +6,8 @@ mod seticon;
 mod theme;
 mod widgets;
 
+use std::io::Read;
+
 use druid::{piet::Color, AppDelegate, AppLauncher, Command, DelegateCtx, Env, LocalizedString, Target, WindowDesc};
 use druid::{Data, Menu, Size, WindowHandle, WindowId};
 
@@ -102,10 +104,15 @@ fn main() -> anyhow::Result<()> {
         .expect("setting default subscriber failed");
     }
 
-    let app_state = if let Some(filename) = std::env::args().nth(1) {
-        NPWindowState::from_file(filename)?
-    } else {
-        NPWindowState::new()
+    let app_state = match std::env::args().nth(1) {
+        // `-` opens the text of the standard input in an untitled buffer
+        Some(arg) if arg == "-" => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            NPWindowState::from_text(&text)
+        }
+        Some(filename) => NPWindowState::from_file(filename)?,
+        None => NPWindowState::new(),
     };
 
     let win = WindowDesc::new(widgets::window::NPWindow::build())
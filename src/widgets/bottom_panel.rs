@@ -1,16 +1,25 @@
 use druid::{
-    widget::{Controller, Flex, Label, TextBox, ViewSwitcher},
-    Data, Env, Event, EventCtx, KbKey, KeyEvent, Lens, Selector, Widget, WidgetExt, WidgetId,
+    widget::{Button, Checkbox, Controller, Flex, Label, TextBox, ViewSwitcher},
+    Data, Env, Event, EventCtx, KbKey, KeyEvent, Lens, Selector, UpdateCtx, Widget, WidgetExt, WidgetId,
 };
 
-use crate::widgets::{EmptyWidget, Extension, PaletteViewState};
+use crate::widgets::{
+    find_in_files::{self, FindInFilesState},
+    text_buffer::search::SearchQuery,
+    EmptyWidget, Extension, PaletteViewState,
+};
 
 pub const PANEL_CLOSED: usize = 0x0;
 pub const PANEL_SEARCH: usize = 0x1;
+pub const PANEL_FIND_IN_FILES: usize = 0x2;
 
 pub const SHOW_SEARCH_PANEL: Selector<String> = Selector::new("nonepad.bottom_panel.show_search");
+/// Show the find in files panel, with the pattern and the folder to search in
+pub const SHOW_FIND_IN_FILES_PANEL: Selector<(String, String)> =
+    Selector::new("nonepad.bottom_panel.show_find_in_files");
 pub const SEND_STRING_DATA: Selector<String> = Selector::new("nonepad.all.send_data");
 pub const CLOSE_BOTTOM_PANEL: Selector<()> = Selector::new("nonepad.bottom_panel.close");
+pub const UPDATE_MATCH_COUNT: Selector<String> = Selector::new("nonepad.bottom_panel.update_match_count");
 
 pub struct BottomPanel {}
 
@@ -18,6 +27,7 @@ pub struct BottomPanel {}
 pub struct BottonPanelState {
     pub current: usize,
     search_state: SearchState,
+    find_in_files_state: FindInFilesState,
     panel_state: PaletteViewState,
 }
 
@@ -33,6 +43,7 @@ pub fn build() -> impl Widget<BottonPanelState> {
         |selector, _data, _env| match *selector {
             PANEL_CLOSED => Box::new(EmptyWidget::default()),
             PANEL_SEARCH => Box::new(build_search_panel().lens(BottonPanelState::search_state)),
+            PANEL_FIND_IN_FILES => Box::new(find_in_files::build_panel().lens(BottonPanelState::find_in_files_state)),
             _ => unreachable!(),
         },
     );
@@ -40,23 +51,48 @@ pub fn build() -> impl Widget<BottonPanelState> {
     view_switcher.with_id(panel_id).controller(BottomPanel {})
 }
 
+impl BottomPanel {
+    fn close(&self, ctx: &mut EventCtx, data: &mut BottonPanelState) {
+        if data.current == PANEL_SEARCH {
+            ctx.submit_command(super::editor_view::UPDATE_SEARCH_HIGHLIGHT.with(None));
+        }
+        data.current = PANEL_CLOSED;
+        ctx.focus_prev();
+    }
+}
+
 impl<W: Widget<BottonPanelState>> Controller<BottonPanelState, W> for BottomPanel {
     fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut BottonPanelState, env: &Env) {
         match event {
             Event::KeyDown(KeyEvent { key: KbKey::Escape, .. }) => {
-                data.current = PANEL_CLOSED;
-                ctx.focus_prev();
+                self.close(ctx, data);
                 return;
             }
             Event::Command(cmd) if cmd.is(CLOSE_BOTTOM_PANEL) => {
-                data.current = PANEL_CLOSED;
-                ctx.focus_prev();
+                self.close(ctx, data);
                 return;
             }
             Event::Command(cmd) if cmd.is(SHOW_SEARCH_PANEL) => {
                 data.current = PANEL_SEARCH;
                 let input = cmd.get_unchecked(SHOW_SEARCH_PANEL).clone();
                 ctx.submit_command(SEND_STRING_DATA.with(input).to(ctx.widget_id()));
+                ctx.submit_command(super::editor_view::UPDATE_SEARCH_HIGHLIGHT.with(Some(data.search_state.query())));
+                return;
+            }
+            Event::Command(cmd) if cmd.is(SHOW_FIND_IN_FILES_PANEL) => {
+                if data.current == PANEL_SEARCH {
+                    ctx.submit_command(super::editor_view::UPDATE_SEARCH_HIGHLIGHT.with(None));
+                }
+                data.current = PANEL_FIND_IN_FILES;
+                let (pattern, folder) = cmd.get_unchecked(SHOW_FIND_IN_FILES_PANEL).clone();
+                if data.find_in_files_state.folder.is_empty() {
+                    data.find_in_files_state.folder = folder;
+                }
+                ctx.submit_command(SEND_STRING_DATA.with(pattern).to(ctx.widget_id()));
+                return;
+            }
+            Event::Command(cmd) if cmd.is(UPDATE_MATCH_COUNT) => {
+                data.search_state.match_count = cmd.get_unchecked(UPDATE_MATCH_COUNT).clone();
                 return;
             }
             _ => (),
@@ -75,27 +111,127 @@ impl<W: Widget<BottonPanelState>> Controller<BottonPanelState, W> for BottomPane
     }
 }
 
-#[derive(Debug, Clone, Data, Lens, Default)]
+#[derive(Debug, Clone, Data, Lens)]
 struct SearchState {
     s: String,
+    replacement: String,
+    regex: bool,
+    case_sensitive: bool,
+    whole_word: bool,
+    wrap_around: bool,
+    match_count: String,
+}
+
+impl Default for SearchState {
+    fn default() -> Self {
+        SearchState {
+            s: String::new(),
+            replacement: String::new(),
+            regex: false,
+            case_sensitive: false,
+            whole_word: false,
+            wrap_around: true,
+            match_count: String::new(),
+        }
+    }
+}
+
+impl SearchState {
+    fn query(&self) -> SearchQuery {
+        SearchQuery {
+            pattern: self.s.clone(),
+            regex: self.regex,
+            case_sensitive: self.case_sensitive,
+            whole_word: self.whole_word,
+            wrap_around: self.wrap_around,
+        }
+    }
+
+    fn replace_request(&self) -> (SearchQuery, String) {
+        (self.query(), self.replacement.clone())
+    }
+}
+
+pub(super) fn option_checkbox(text: &str) -> Checkbox {
+    Checkbox::from_label(Label::new(text).with_text_size(12.0))
+}
+
+pub(super) fn panel_label<T: Data>(text: &str) -> impl Widget<T> {
+    Label::new(text).with_text_size(12.0).fix_width(60.0)
+}
+
+/// Send the query to the editor each time it changes, for incremental search
+struct SearchPanel;
+
+impl<W: Widget<SearchState>> Controller<SearchState, W> for SearchPanel {
+    fn update(&mut self, child: &mut W, ctx: &mut UpdateCtx, old_data: &SearchState, data: &SearchState, env: &Env) {
+        if old_data.query() != data.query() {
+            ctx.submit_command(super::editor_view::UPDATE_SEARCH_HIGHLIGHT.with(Some(data.query())));
+        }
+        child.update(ctx, old_data, data, env)
+    }
 }
 
 fn build_search_panel() -> impl Widget<SearchState> {
+    Flex::column()
+        .with_child(build_search_row())
+        .with_spacer(2.0)
+        .with_child(build_replace_row())
+        .controller(SearchPanel)
+}
+
+fn build_search_row() -> impl Widget<SearchState> {
     Flex::row()
-        .with_child(Label::new("Search").with_text_size(12.0))
+        .with_child(panel_label("Search"))
         .with_flex_child(
             TextBox::new()
                 .with_text_size(12.0)
-                .on_enter(|ctx, data: &mut String, _| {
-                    ctx.submit_command(super::editor_view::REQUEST_NEXT_SEARCH.with(data.clone()))
-                })
                 .focus()
                 .on_data_received(|ctx, state: &mut String, data: &String, _| {
                     ctx.request_focus();
                     state.clone_from(data);
                 })
                 .lens(SearchState::s)
+                .on_enter(|ctx, data: &mut SearchState, _| {
+                    ctx.submit_command(super::editor_view::REQUEST_NEXT_SEARCH.with(data.query()))
+                })
+                .on_shift_enter(|ctx, data: &mut SearchState, _| {
+                    ctx.submit_command(super::editor_view::REQUEST_PREV_SEARCH.with(data.query()))
+                })
+                .expand_width(),
+            1.0,
+        )
+        .with_child(
+            Label::new(|data: &SearchState, _env: &Env| data.match_count.clone())
+                .with_text_size(12.0)
+                .fix_width(100.0),
+        )
+        .with_child(option_checkbox("Match case").lens(SearchState::case_sensitive))
+        .with_child(option_checkbox("Whole word").lens(SearchState::whole_word))
+        .with_child(option_checkbox("Regex").lens(SearchState::regex))
+        .with_child(option_checkbox("Wrap around").lens(SearchState::wrap_around))
+}
+
+fn build_replace_row() -> impl Widget<SearchState> {
+    Flex::row()
+        .with_child(panel_label("Replace"))
+        .with_flex_child(
+            TextBox::new()
+                .with_text_size(12.0)
+                .lens(SearchState::replacement)
+                .on_enter(|ctx, data: &mut SearchState, _| {
+                    ctx.submit_command(super::editor_view::REQUEST_REPLACE.with(data.replace_request()))
+                })
                 .expand_width(),
             1.0,
         )
+        .with_child(Button::new("Replace").on_click(|ctx, data: &mut SearchState, _| {
+            ctx.submit_command(super::editor_view::REQUEST_REPLACE.with(data.replace_request()))
+        }))
+        .with_child(Button::new("Replace All").on_click(|ctx, data: &mut SearchState, _| {
+            ctx.submit_command(super::editor_view::REQUEST_REPLACE_ALL.with(data.replace_request()))
+        }))
+        .with_child(Button::new("In selection").on_click(|ctx, data: &mut SearchState, _| {
+            ctx.submit_command(super::editor_view::REQUEST_REPLACE_IN_SELECTION.with(data.replace_request()))
+        }))
 }
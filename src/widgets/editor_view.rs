@@ -1,11 +1,18 @@
 use std::ops::{Deref, DerefMut, Range};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-use super::text_buffer::syntax::{StateCache, StyledLinesCache, SYNTAXSET};
+use super::bottom_panel;
+use super::find_in_files::{self, ReplaceInFiles};
+use super::text_buffer::buffer::Buffer;
+use super::text_buffer::position::Position;
+use super::text_buffer::search::{Matcher, SearchQuery};
+use super::text_buffer::syntax::{self, StateCache, StyledLinesCache, SYNTAXSET};
 use super::text_buffer::{position, rope_utils, EditStack, SelectionLineRange};
+use super::window::SHOW_STATUS;
 use super::{PaletteCommandType, PALETTE_CALLBACK};
 
 use crate::commands::{self, UICommandEventHandler};
@@ -43,13 +50,31 @@ pub const FONT_SIZE: f64 = 14.;
 pub const FONT_WEIGTH: FontWeight = FontWeight::SEMI_BOLD;
 pub const EDITOR_LEFT_PADDING: f64 = 2.;
 pub const SCROLLBAR_X_PADDING: f64 = 2.;
-
-pub const REQUEST_NEXT_SEARCH: Selector<String> = Selector::new("nonepad.editor.request_next_search");
+/// The colors of the brackets by nesting depth, when the rainbow brackets are on
+const RAINBOW_COLORS: [Color; 3] = [
+    Color::rgb8(0xFF, 0xD7, 0x00),
+    Color::rgb8(0xDA, 0x70, 0xD6),
+    Color::rgb8(0x17, 0x9F, 0xFF),
+];
+
+pub const REQUEST_NEXT_SEARCH: Selector<SearchQuery> = Selector::new("nonepad.editor.request_next_search");
+pub const REQUEST_PREV_SEARCH: Selector<SearchQuery> = Selector::new("nonepad.editor.request_prev_search");
+/// Update (or clear, with `None`) the search matches highlighted in the editor
+pub const UPDATE_SEARCH_HIGHLIGHT: Selector<Option<SearchQuery>> =
+    Selector::new("nonepad.editor.update_search_highlight");
+/// Open a file (if not already opened), navigate to the line and select the byte range
+pub const OPEN_FILE_AT: Selector<(PathBuf, usize, Range<usize>)> = Selector::new("nonepad.editor.open_file_at");
+pub const REPLACE_IN_FILES: Selector<ReplaceInFiles> = Selector::new("nonepad.editor.replace_in_files");
+pub const REQUEST_REPLACE: Selector<(SearchQuery, String)> = Selector::new("nonepad.editor.request_replace");
+pub const REQUEST_REPLACE_ALL: Selector<(SearchQuery, String)> = Selector::new("nonepad.editor.request_replace_all");
+pub const REQUEST_REPLACE_IN_SELECTION: Selector<(SearchQuery, String)> =
+    Selector::new("nonepad.editor.request_replace_in_selection");
 
 const SCROLL_TO: Selector<(Option<f64>, Option<f64>)> = Selector::new("nonepad.editor.scroll_to_rect");
 const SELECT_LINE: Selector<(usize, bool)> = Selector::new("nonepad.editor.select_line");
 const HIGHLIGHT: Selector<(usize, usize)> = Selector::new("nonepad.editor.highlight");
 const RELOAD_FROM_DISK: Selector<()> = Selector::new("nonepad.editor.reload_from_disk");
+const SEARCH_MATCHES: Selector<SearchMatches> = Selector::new("nonepad.editor.search_matches");
 const FILE_REMOVED: Selector<()> = Selector::new("nonepad.editor.file_removed");
 
 
@@ -152,6 +177,8 @@ enum HeldState {
     Grapheme,
     Word,
     Line,
+    /// Alt+drag block selection, from the line and visual column where the drag started
+    Block(position::Line, position::Column),
 }
 
 impl HeldState {
@@ -165,6 +192,15 @@ pub enum BackgroundWorkerMessage {
     UpdateBuffer(SyntaxReference, Rope, usize),
     WatchFile(PathBuf),
     UnwatchFile(PathBuf),
+    Search(Matcher, Buffer),
+}
+
+/// All the matches of the search query, computed by the background worker
+#[derive(Debug, Clone)]
+struct SearchMatches {
+    /// The buffer the matches were computed on, they are stale if the content differ
+    buffer: Buffer,
+    ranges: Arc<Vec<Range<position::Absolute>>>,
 }
 
 #[derive(Debug)]
@@ -189,6 +225,13 @@ pub struct EditorView {
 
     bgworker_channel_tx: Option<Sender<BackgroundWorkerMessage>>,
     highlighted_line: StyledLinesCache,
+
+    search_query: Option<SearchQuery>,
+    search_matcher: Option<Matcher>,
+    search_matches: Option<SearchMatches>,
+    search_status: String,
+
+    rainbow_brackets: bool,
 }
 
 impl Widget<EditStack> for EditorView {
@@ -226,6 +269,7 @@ impl Widget<EditStack> for EditorView {
                     let mut chunk_len = 100;
                     let mut rope = Rope::new();
                     let mut hotwatch = hotwatch::Hotwatch::new().unwrap(); // TODO, will crash the highlighter if hotwatch couldn't be initialized
+                    let mut pending_search = None;
                     loop {
                         match rx.try_recv() {
                             Ok(message) => match message {
@@ -252,8 +296,27 @@ impl Widget<EditStack> for EditorView {
                                 BackgroundWorkerMessage::UnwatchFile(p) => {
                                     let _ = hotwatch.unwatch(p);
                                 }
+                                BackgroundWorkerMessage::Search(matcher, buffer) => {
+                                    pending_search = Some((matcher, buffer));
+                                }
                             },
-                            _ => (),
+                            _ => {
+                                // Only search once the queue is empty, to skip the queries outdated while typing
+                                if let Some((matcher, buffer)) = pending_search.take() {
+                                    let ranges = matcher
+                                        .find_iter(&buffer.rope, 0.into()..buffer.len())
+                                        .filter(|r| !r.is_empty())
+                                        .collect();
+                                    let _ = event_sink.submit_command(
+                                        SEARCH_MATCHES,
+                                        SearchMatches {
+                                            buffer,
+                                            ranges: Arc::new(ranges),
+                                        },
+                                        owner_id,
+                                    );
+                                }
+                            }
                         }
                         if current_index < rope.len_lines() {
                             highlight_cache.update_range(
@@ -306,7 +369,13 @@ impl Widget<EditStack> for EditorView {
         if !old_data.file.syntax.name.same(&data.file.syntax.name) {
             self.update_highlighter(data, 0);
         }
+        if !old_data.buffer.same_content(&data.buffer) {
+            self.update_search(data);
+        }
         if !old_data.same(data) {
+            if let Some(status) = self.search_status_changed(data) {
+                ctx.submit_command(bottom_panel::UPDATE_MATCH_COUNT.with(status));
+            }
             ctx.request_paint();
         }
         match (&old_data.filename, &data.filename) {
@@ -363,6 +432,11 @@ impl EditorView {
             held_state: HeldState::None,
             bgworker_channel_tx: None,
             highlighted_line: StyledLinesCache::new(),
+            search_query: None,
+            search_matcher: None,
+            search_matches: None,
+            search_status: String::new(),
+            rainbow_brackets: false,
         };
 
         e
@@ -394,6 +468,43 @@ impl EditorView {
         }
     }
 
+    fn update_search(&self, editor: &EditStack) {
+        if let (Some(matcher), Some(tx)) = (&self.search_matcher, &self.bgworker_channel_tx) {
+            let _ = tx.send(BackgroundWorkerMessage::Search(matcher.clone(), editor.buffer.clone()));
+        }
+    }
+
+    /// The up-to-date matches for the current search, if any
+    fn valid_search_matches(&self, editor: &EditStack) -> Option<Arc<Vec<Range<position::Absolute>>>> {
+        self.search_matches
+            .as_ref()
+            .filter(|m| m.buffer.same_content(&editor.buffer))
+            .map(|m| m.ranges.clone())
+    }
+
+    /// Return the new "N of M" status if it changed. `None` also while the matches are being computed.
+    fn search_status_changed(&mut self, editor: &EditStack) -> Option<String> {
+        let status = match (&self.search_query, &self.search_matcher) {
+            (None, _) => String::new(),
+            (Some(_), None) => "Invalid pattern".to_owned(),
+            (Some(_), Some(_)) => {
+                let matches = self.valid_search_matches(editor)?;
+                let caret = editor.main_caret().range();
+                match matches.binary_search_by_key(&caret.start, |r| r.start) {
+                    _ if matches.is_empty() => "No results".to_owned(),
+                    Ok(i) if matches[i] == caret => format!("{} of {}", i + 1, matches.len()),
+                    _ => format!("{} matches", matches.len()),
+                }
+            }
+        };
+        if status != self.search_status {
+            self.search_status = status.clone();
+            Some(status)
+        } else {
+            None
+        }
+    }
+
     fn stop_background_worker(&self) {
         if let Some(tx) = self.bgworker_channel_tx.clone() {
             match tx.send(BackgroundWorkerMessage::Stop) {
@@ -417,40 +528,36 @@ impl EditorView {
             }
             Event::KeyDown(event) => {
                 match event {
-                    #[cfg(windows)]
                     KeyEvent {
                         key: druid::keyboard_types::Key::ArrowDown,
                         mods,
                         ..
-                    } if mods.alt() && mods.ctrl() => {
-                        editor.duplicate_down();
+                    } if mods.alt() && mods.shift() => {
+                        editor.move_block_head(1, 0);
                         return true;
                     }
-                    #[cfg(windows)]
                     KeyEvent {
                         key: druid::keyboard_types::Key::ArrowUp,
                         mods,
                         ..
-                    } if mods.alt() && mods.ctrl() => {
-                        editor.duplicate_up();
+                    } if mods.alt() && mods.shift() => {
+                        editor.move_block_head(-1, 0);
                         return true;
                     }
-                    #[cfg(not(windows))]
                     KeyEvent {
-                        key: druid::keyboard_types::Key::ArrowDown,
+                        key: druid::keyboard_types::Key::ArrowRight,
                         mods,
                         ..
                     } if mods.alt() && mods.shift() => {
-                        editor.duplicate_down();
+                        editor.move_block_head(0, 1);
                         return true;
                     }
-                    #[cfg(not(windows))]
                     KeyEvent {
-                        key: druid::keyboard_types::Key::ArrowUp,
+                        key: druid::keyboard_types::Key::ArrowLeft,
                         mods,
                         ..
                     } if mods.alt() && mods.shift() => {
-                        editor.duplicate_up();
+                        editor.move_block_head(0, -1);
                         return true;
                     }
                     KeyEvent {
@@ -523,9 +630,14 @@ impl EditorView {
                     }
                     KeyEvent {
                         key: druid::keyboard_types::Key::Tab,
+                        mods,
                         ..
                     } => {
-                        editor.tab();
+                        if mods.shift() {
+                            editor.outdent();
+                        } else {
+                            editor.tab();
+                        }
                         return true;
                     }
                     KeyEvent {
@@ -568,7 +680,8 @@ impl EditorView {
                         return false;
                     }
 
-                    editor.insert(&text);
+                    let highlighted_line = self.highlighted_line.lines.lock().unwrap();
+                    editor.type_text(&text, &highlighted_line);
                     return true;
                 }
                 false
@@ -593,7 +706,12 @@ impl EditorView {
                 true
             }
             Event::MouseDown(event) => {
-                if matches!(event.button, MouseButton::Left) {
+                if matches!(event.button, MouseButton::Left) && event.mods.alt() {
+                    let (col, line) = self.pix_to_visual_column(event.pos.x, event.pos.y, ctx, editor);
+                    editor.select_block((line, col), (line, col));
+                    self.held_state = HeldState::Block(line, col);
+                    ctx.set_active(true);
+                } else if matches!(event.button, MouseButton::Left) {
                     let (x, y) = self.pix_to_point(event.pos.x, event.pos.y, ctx, editor);
                     editor.cancel_mutli_carets();
                     // FIXME: Update is not called if the caret position is not modified,
@@ -631,13 +749,18 @@ impl EditorView {
             }
             Event::MouseMove(event) => {
                 if self.held_state.is_held() && ctx.is_active() && event.buttons.contains(MouseButton::Left) {
+                    if let HeldState::Block(line, col) = self.held_state {
+                        let head = self.pix_to_visual_column(event.pos.x, event.pos.y, ctx, editor);
+                        editor.select_block((line, col), (head.1, head.0));
+                        return true;
+                    }
                     let (x, y) = self.pix_to_point(event.pos.x, event.pos.y, ctx, editor);
                     let p = editor.point(x, y);
                     match self.held_state {
                         HeldState::Grapheme => editor.move_main_caret_to(p, true, false),
                         HeldState::Word => editor.move_main_caret_to(p, true, true),
                         HeldState::Line => editor.select_line(p.line, true),
-                        HeldState::None => unreachable!(),
+                        HeldState::None | HeldState::Block(..) => unreachable!(),
                     }
                     return true;
                 }
@@ -685,8 +808,108 @@ impl EditorView {
                 true
             }
             Event::Command(cmd) if cmd.is(REQUEST_NEXT_SEARCH) => {
-                if let Some(data) = cmd.get(REQUEST_NEXT_SEARCH) {
-                    editor.search_next(data);
+                let query = cmd.get_unchecked(REQUEST_NEXT_SEARCH);
+                self.search(ctx, editor, query, false);
+                true
+            }
+            Event::Command(cmd) if cmd.is(REQUEST_PREV_SEARCH) => {
+                let query = cmd.get_unchecked(REQUEST_PREV_SEARCH);
+                self.search(ctx, editor, query, true);
+                true
+            }
+            Event::Command(cmd) if cmd.is(OPEN_FILE_AT) => {
+                let (path, line, range) = cmd.get_unchecked(OPEN_FILE_AT).clone();
+                if editor.is_dirty() && !editor.is_file(&path) {
+                    self.dialog()
+                        .title("Discard unsaved change?")
+                        .on_select(move |result, ctx, editor_view, data| {
+                            if result == DialogResult::Ok {
+                                editor_view.open_at(ctx, data, &path, line, range.clone());
+                            }
+                        })
+                        .show(ctx);
+                } else {
+                    self.open_at(ctx, editor, &path, line, range);
+                }
+                true
+            }
+            Event::Command(cmd) if cmd.is(REPLACE_IN_FILES) => {
+                let request = cmd.get_unchecked(REPLACE_IN_FILES);
+                let mut count = 0;
+                let mut files = 0;
+                let mut errors = Vec::new();
+                for (path, ranges) in &request.files {
+                    let result = if editor.is_file(path) {
+                        // the opened file get an undoable edit instead of being overwritten
+                        let ranges: Vec<_> = ranges
+                            .iter()
+                            .map(|r| position::Absolute::from(r.start)..position::Absolute::from(r.end))
+                            .collect();
+                        Ok(editor.replace_matches(&request.matcher, &request.replacement, &ranges))
+                    } else {
+                        find_in_files::replace_in_file(path, &request.matcher, &request.replacement, ranges)
+                    };
+                    match result {
+                        Ok(0) => (),
+                        Ok(c) => {
+                            count += c;
+                            files += 1;
+                        }
+                        Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+                    }
+                }
+                ctx.submit_command(SHOW_STATUS.with(format!("Replaced {} occurrence(s) in {} file(s)", count, files)));
+                if !errors.is_empty() {
+                    self.alert(&format!("Error while replacing in files\n{}", errors.join("\n")))
+                        .show(ctx);
+                }
+                true
+            }
+            Event::Command(cmd) if cmd.is(UPDATE_SEARCH_HIGHLIGHT) => {
+                self.search_query = cmd
+                    .get_unchecked(UPDATE_SEARCH_HIGHLIGHT)
+                    .clone()
+                    .filter(|q| !q.is_empty());
+                self.search_matcher = self.search_query.as_ref().and_then(|q| Matcher::new(q).ok());
+                self.search_matches = None;
+                self.update_search(editor);
+                if let Some(status) = self.search_status_changed(editor) {
+                    ctx.submit_command(bottom_panel::UPDATE_MATCH_COUNT.with(status));
+                }
+                ctx.request_paint();
+                true
+            }
+            Event::Command(cmd) if cmd.is(SEARCH_MATCHES) => {
+                let matches = cmd.get_unchecked(SEARCH_MATCHES);
+                if self.search_matcher.is_some() && matches.buffer.same_content(&editor.buffer) {
+                    self.search_matches = Some(matches.clone());
+                    if let Some(status) = self.search_status_changed(editor) {
+                        ctx.submit_command(bottom_panel::UPDATE_MATCH_COUNT.with(status));
+                    }
+                    ctx.request_paint();
+                }
+                true
+            }
+            Event::Command(cmd) if cmd.is(REQUEST_REPLACE) => {
+                let (query, replacement) = cmd.get_unchecked(REQUEST_REPLACE);
+                if let Some(matcher) = self.matcher(ctx, query) {
+                    editor.replace_next(&matcher, replacement, query.wrap_around);
+                }
+                true
+            }
+            Event::Command(cmd) if cmd.is(REQUEST_REPLACE_ALL) => {
+                let (query, replacement) = cmd.get_unchecked(REQUEST_REPLACE_ALL);
+                if let Some(matcher) = self.matcher(ctx, query) {
+                    let count = editor.replace_all(&matcher, replacement);
+                    ctx.submit_command(SHOW_STATUS.with(format!("Replaced {} occurrence(s)", count)));
+                }
+                true
+            }
+            Event::Command(cmd) if cmd.is(REQUEST_REPLACE_IN_SELECTION) => {
+                let (query, replacement) = cmd.get_unchecked(REQUEST_REPLACE_IN_SELECTION);
+                if let Some(matcher) = self.matcher(ctx, query) {
+                    let count = editor.replace_in_selection(&matcher, replacement);
+                    ctx.submit_command(SHOW_STATUS.with(format!("Replaced {} occurrence(s) in selection", count)));
                 }
                 true
             }
@@ -958,6 +1181,49 @@ impl EditorView {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
+    fn add_search_match_rects<L: TextLayout>(
+        &self,
+        editor: &EditStack,
+        line_idx: usize,
+        y: f64,
+        matches: &[Range<position::Absolute>],
+        indices: &[position::Relative],
+        layout: &L,
+        rects: &mut Vec<(Rect, bool)>,
+    ) {
+        if line_idx >= editor.len_lines() {
+            return;
+        }
+        let line = position::Line::from(line_idx);
+        let start = line.start(&editor.buffer);
+        let end = line.end(&editor.buffer);
+        let main_range = editor.main_caret().range();
+
+        // matches are sorted and don't overlap, so their ends are sorted too
+        let first = matches.partition_point(|m| m.end < start);
+        for m in matches[first..].iter().take_while(|m| m.start <= end) {
+            let (Some(s), Some(e)) = (
+                indices.get((m.start.max(start) - start).index),
+                indices.get((m.end.min(end) - start).index),
+            ) else {
+                continue;
+            };
+            let x0 = layout.hit_test_text_position(s.index).point.x;
+            let mut x1 = layout.hit_test_text_position(e.index).point.x;
+            if m.end > end {
+                // the match continues on the next line, show the line feed as matched
+                x1 += self.metrics.font_advance;
+            }
+            if x1 > x0 {
+                rects.push((
+                    Rect::new(x0.ceil(), y.ceil(), x1.ceil(), (y + self.metrics.font_height).ceil()),
+                    *m == main_range,
+                ));
+            }
+        }
+    }
+
     fn paint_editor(&mut self, editor: &EditStack, ctx: &mut PaintCtx, env: &Env) -> bool {
         let font = ctx.render_ctx.text().font_family(&self.font_name).unwrap();
         let rect = Rect::new(0.0, 0.0, self.size.width, self.size.height);
@@ -973,6 +1239,11 @@ impl EditorView {
         let mut ranges = Vec::new();
         let mut selection_path = Vec::new();
         let mut current_path = SelectionPath::new();
+        let search_matches = self.valid_search_matches(editor);
+        let mut match_rects = Vec::new();
+        let mut virtual_selection_rects = Vec::new();
+        let bracket_pair = self.main_caret_bracket_pair(editor, self.visible_range());
+        let mut bracket_rects = Vec::new();
 
         // Draw selection first
         // TODO: cache layout to reuse it when we will draw the text
@@ -990,6 +1261,38 @@ impl EditorView {
 
             editor.selection_on_line(line_idx, &mut ranges);
 
+            for (l, bracket) in bracket_pair.iter().flat_map(|p| [p.0, p.1]) {
+                if l == line_idx {
+                    let x0 = layout.hit_test_text_position(indices[bracket.offset].index).point.x;
+                    let x1 = layout.hit_test_text_position(indices[bracket.offset + 1].index).point.x;
+                    bracket_rects.push(Rect::new(
+                        x0.floor() + 0.5,
+                        dy.ceil() + 0.5,
+                        x1.ceil() - 0.5,
+                        (dy + self.metrics.font_height).ceil() - 0.5,
+                    ));
+                }
+            }
+
+            if let Some(matches) = &search_matches {
+                self.add_search_match_rects(editor, line_idx, dy, matches, &indices, &layout, &mut match_rects);
+            }
+
+            // selection past the end of the line, made by a block selection
+            for c in editor.carets_on_line(position::Line::from(line_idx)) {
+                if let Some(v) = c.virtual_selection() {
+                    let l = position::Line::from(line_idx);
+                    let end = (l.end(&editor.buffer) - l.start(&editor.buffer)).index;
+                    let end_x = layout.hit_test_text_position(indices[end].index).point.x;
+                    virtual_selection_rects.push(Rect::new(
+                        (end_x + v.start as f64 * self.metrics.font_advance).ceil(),
+                        dy.ceil(),
+                        (end_x + v.end as f64 * self.metrics.font_advance).ceil(),
+                        (dy + self.metrics.font_height).ceil(),
+                    ));
+                }
+            }
+
             for range in &ranges {
                 match range {
                     SelectionLineRange::Range(r) => {
@@ -1050,6 +1353,28 @@ impl EditorView {
             ctx.render_ctx.stroke(&path, &brush, 1.);
         }
 
+        for rect in virtual_selection_rects {
+            ctx.render_ctx.fill(rect, &self.fg_sel_color);
+        }
+
+        for rect in bracket_rects {
+            ctx.render_ctx.stroke(
+                rect,
+                &env.get(crate::theme::EDITOR_OVERVIEW_RULER_BRACKET_MATCH_FOREGROUND),
+                1.,
+            );
+        }
+
+        for (rect, current) in match_rects {
+            if current {
+                ctx.render_ctx
+                    .fill(rect, &env.get(crate::theme::EDITOR_FIND_MATCH_BACKGROUND));
+            } else {
+                ctx.render_ctx
+                    .fill(rect, &env.get(crate::theme::EDITOR_FIND_MATCH_HIGHLIGHT_BACKGROUND));
+            }
+        }
+
         let mut dy = (self.delta_y / self.metrics.font_height).fract() * self.metrics.font_height;
         for line_idx in self.visible_range() {
             editor.displayable_line(position::Line::from(line_idx), &mut line, &mut indices, &mut Vec::new());
@@ -1081,6 +1406,15 @@ impl EditorView {
                             layout = layout.range_attribute(start.unwrap().index..end.unwrap().index, color);
                         }
                     }
+                    if self.rainbow_brackets {
+                        for b in highlight.brackets.iter() {
+                            if let (Some(start), Some(end)) = (indices.get(b.offset), indices.get(b.offset + 1)) {
+                                let color = RAINBOW_COLORS[b.depth % RAINBOW_COLORS.len()];
+                                layout =
+                                    layout.range_attribute(start.index..end.index, TextAttribute::TextColor(color));
+                            }
+                        }
+                    }
                 }
             }
             let layout = layout.build().unwrap();
@@ -1092,10 +1426,11 @@ impl EditorView {
             if ctx.has_focus() {
                 editor.carets_on_line(position::Line::from(line_idx)).for_each(|c| {
                     let metrics = layout.hit_test_text_position(indices[c.relative().index].index);
+                    let x = metrics.point.x + c.virtual_space() as f64 * self.metrics.font_advance;
                     ctx.render_ctx.stroke(
                         Line::new(
-                            (metrics.point.x.ceil(), (self.metrics.font_height + dy).ceil()),
-                            (metrics.point.x.ceil(), dy.ceil()),
+                            (x.ceil(), (self.metrics.font_height + dy).ceil()),
+                            (x.ceil(), dy.ceil()),
                         ),
                         &env.get(crate::theme::EDITOR_CURSOR_FOREGROUND),
                         2.0,
@@ -1129,6 +1464,27 @@ impl EditorView {
         (rel, line)
     }
 
+    /// Like `pix_to_point`, but past the end of the line, count the columns of virtual space under the mouse
+    fn pix_to_visual_column(
+        &self,
+        x: f64,
+        y: f64,
+        ctx: &mut EventCtx,
+        editor: &EditStack,
+    ) -> (position::Column, position::Line) {
+        let (col, line) = self.pix_to_point(x, y, ctx, editor);
+        let line = position::Line::from(line);
+        let end_col = line.end(&editor.buffer).point(&editor.buffer).col;
+        let x = (x - self.delta_x - EDITOR_LEFT_PADDING).max(0.);
+        let end_x = end_col.index as f64 * self.metrics.font_advance;
+        if col >= end_col.index && x > end_x && self.metrics.font_advance > 0. {
+            let virtual_space = ((x - end_x) / self.metrics.font_advance).round() as usize;
+            (position::Column::from(end_col.index + virtual_space), line)
+        } else {
+            (position::Column::from(col), line)
+        }
+    }
+
     fn text_layout(&self, text: &mut PietText, buf: String) -> impl druid::piet::TextLayout {
         let font = text.font_family(&self.font_name).unwrap();
         text.new_text_layout(buf)
@@ -1173,6 +1529,88 @@ impl EditorView {
         );
     }
 
+    fn matcher(&mut self, ctx: &mut EventCtx, query: &SearchQuery) -> Option<Matcher> {
+        if query.is_empty() {
+            return None;
+        }
+        match Matcher::new(query) {
+            Ok(matcher) => Some(matcher),
+            Err(e) => {
+                self.alert(&format!("Invalid search pattern: {}", e)).show(ctx);
+                None
+            }
+        }
+    }
+
+    fn search(&mut self, ctx: &mut EventCtx, editor: &mut EditStack, query: &SearchQuery, backward: bool) {
+        if let Some(matcher) = self.matcher(ctx, query) {
+            if backward {
+                editor.search_prev(&matcher, query.wrap_around);
+            } else {
+                editor.search_next(&matcher, query.wrap_around);
+            }
+        }
+    }
+
+    /// The bracket next to the main caret and the one matching it, searched in `search_range` lines,
+    /// as line index and bracket
+    fn main_caret_bracket_pair(
+        &self,
+        editor: &EditStack,
+        search_range: Range<usize>,
+    ) -> Option<((usize, syntax::Bracket), (usize, syntax::Bracket))> {
+        let caret = editor.main_caret();
+        if !caret.selection_is_empty() {
+            return None;
+        }
+        let line = caret.line().index;
+        let offset = caret.index.index - editor.rope.line_to_byte(line);
+        let lines = self.highlighted_line.lines.lock().unwrap();
+        let pair = syntax::matching_bracket(&lines, line, offset, search_range)?;
+        // the highlighting may be late on the last edit
+        let is_in_buffer = |(l, b): &(usize, syntax::Bracket)| {
+            *l < editor.len_lines() && editor.rope.line(*l).get_byte(b.offset) == Some(b.char as u8)
+        };
+        (is_in_buffer(&pair.0) && is_in_buffer(&pair.1)).then_some(pair)
+    }
+
+    /// Move the main caret before the bracket matching the one next to it
+    pub fn goto_matching_bracket(&mut self, editor: &mut EditStack) {
+        if let Some((_, (line, bracket))) = self.main_caret_bracket_pair(editor, 0..editor.len_lines()) {
+            let index = editor.rope.line_to_byte(line) + bracket.offset;
+            editor.cancel_mutli_carets();
+            editor.move_main_caret_to(position::Absolute::from(index), false, false);
+        }
+    }
+
+    /// Select the tag name at the main caret and the one of its partner, to rename them together
+    pub fn select_tag_pair(&mut self, editor: &mut EditStack) -> bool {
+        let lines = self.highlighted_line.lines.lock().unwrap();
+        editor.select_tag_pair(&lines)
+    }
+
+    /// Replace the brackets, quotes or tag around each caret by `surrounding`, or remove them if it is `None`.
+    /// Returns false if `surrounding` can't be parsed.
+    pub fn change_surrounding(&mut self, editor: &mut EditStack, surrounding: Option<&str>) -> bool {
+        let lines = self.highlighted_line.lines.lock().unwrap();
+        match surrounding {
+            Some(surrounding) => editor.change_surrounding(surrounding, &lines),
+            None => {
+                editor.delete_surrounding(&lines);
+                true
+            }
+        }
+    }
+
+    pub fn toggle_rainbow_brackets(&mut self) {
+        self.rainbow_brackets = !self.rainbow_brackets;
+    }
+
+    /// Show `message` in the status bar, until the next key press
+    pub fn show_status(&self, ctx: &mut EventCtx, message: &str) {
+        ctx.submit_command(SHOW_STATUS.with(message.to_owned()));
+    }
+
     pub fn navigate_to_line(&mut self, ctx: &mut EventCtx, editor: &mut EditStack, line: position::Line) {
         if line.index < editor.len_lines() {
             let start = line.start(&editor.buffer);
@@ -1182,6 +1620,21 @@ impl EditorView {
         }
     }
 
+    fn open_at(&mut self, ctx: &mut EventCtx, editor: &mut EditStack, path: &Path, line: usize, range: Range<usize>) {
+        if !editor.is_file(path) {
+            if let Err(e) = self.open(editor, path) {
+                self.alert(&format!("Error loading file: {}", e)).show(ctx);
+                return;
+            }
+        }
+        self.navigate_to_line(ctx, editor, line.into());
+        // the file may have changed since the search
+        if range.end <= editor.len().index {
+            editor.move_main_caret_to(position::Absolute::from(range.start), false, false);
+            editor.move_main_caret_to(position::Absolute::from(range.end), true, false);
+        }
+    }
+
     fn save_as<P>(&mut self, editor: &mut EditStack, filename: P) -> anyhow::Result<()>
     where
         P: AsRef<Path>,
@@ -5,18 +5,29 @@ use druid::{
 
 pub struct OnEnter<T> {
     action: Box<dyn Fn(&mut EventCtx, &mut T, &Env)>,
+    shift: bool,
 }
 impl<T: Data> OnEnter<T> {
     pub fn new(action: impl Fn(&mut EventCtx, &mut T, &Env) + 'static) -> Self {
         OnEnter {
             action: Box::new(action),
+            shift: false,
+        }
+    }
+    pub fn with_shift(action: impl Fn(&mut EventCtx, &mut T, &Env) + 'static) -> Self {
+        OnEnter {
+            action: Box::new(action),
+            shift: true,
         }
     }
 }
 
 impl<T: Data, W: Widget<T>> Controller<T, W> for OnEnter<T> {
     fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut T, env: &Env) {
-        if let Event::KeyDown(KeyEvent { key: KbKey::Enter, .. }) = event {
+        if let Event::KeyDown(KeyEvent { key: KbKey::Enter, mods, .. }) = event {
+            if mods.shift() != self.shift {
+                return child.event(ctx, event, data, env);
+            }
             (self.action)(ctx, data, env);
             ctx.set_handled();            
         } else {
@@ -87,6 +98,9 @@ pub trait Extension<T: Data>: Widget<T> + Sized + 'static {
     fn on_enter(self, f: impl Fn(&mut EventCtx, &mut T, &Env) + 'static) -> ControllerHost<Self, OnEnter<T>> {
         ControllerHost::new(self, OnEnter::new(f))
     }
+    fn on_shift_enter(self, f: impl Fn(&mut EventCtx, &mut T, &Env) + 'static) -> ControllerHost<Self, OnEnter<T>> {
+        ControllerHost::new(self, OnEnter::with_shift(f))
+    }
     fn focus(self) -> ControllerHost<Self, TakeFocus> {
         ControllerHost::new(self, TakeFocus::new())
     }
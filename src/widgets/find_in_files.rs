@@ -0,0 +1,476 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use druid::{
+    im::Vector,
+    lens,
+    widget::{Button, Checkbox, Controller, Either, Flex, Label, List, Painter, Scroll, TextBox},
+    Data, Env, Event, EventCtx, ExtEventSink, KbKey, KeyEvent, Lens, LensExt, RenderContext, Selector, Widget,
+    WidgetExt, WidgetId,
+};
+use ignore::WalkBuilder;
+
+use super::{
+    bottom_panel::{option_checkbox, panel_label},
+    editor_view,
+    text_buffer::{
+        buffer::Buffer,
+        position::Absolute,
+        search::{Matcher, SearchQuery},
+        TextFileInfo,
+    },
+    Extension,
+};
+
+/// Stop the search after this many matches, the results list would be unusable anyway
+const MAX_RESULTS: usize = 10_000;
+const PREVIEW_LEN: usize = 200;
+
+/// Start a search, previewing the replacements if `true`
+const START_SEARCH: Selector<bool> = Selector::new("nonepad.find_in_files.start_search");
+const APPLY_REPLACE: Selector<()> = Selector::new("nonepad.find_in_files.apply_replace");
+const FILE_MATCHES: Selector<(u64, FileMatches)> = Selector::new("nonepad.find_in_files.file_matches");
+const SEARCH_DONE: Selector<(u64, usize)> = Selector::new("nonepad.find_in_files.search_done");
+
+#[derive(Clone)]
+struct FileMatches {
+    path: PathBuf,
+    matches: Vec<LineMatch>,
+}
+
+#[derive(Clone)]
+struct LineMatch {
+    line: usize,
+    range: Range<usize>,
+    preview: String,
+    /// The line after the replacement of this match
+    replaced: Option<String>,
+}
+
+/// The checked replacements, applied by the editor so the opened file get an undoable edit
+#[derive(Clone)]
+pub struct ReplaceInFiles {
+    pub matcher: Matcher,
+    pub replacement: String,
+    pub files: Vec<(PathBuf, Vec<Range<usize>>)>,
+}
+
+fn preview(line: &str) -> String {
+    line.trim().chars().take(PREVIEW_LEN).collect()
+}
+
+/// Search one file, decoded with the same encoding detection as the editor
+fn search_file(path: &Path, matcher: &Matcher, replacement: Option<&str>) -> std::io::Result<Vec<LineMatch>> {
+    let (_, rope) = TextFileInfo::load(path)?;
+    // Don't bother with binary files
+    if rope.chars().take(8000).any(|c| c == '\0') {
+        return Ok(Vec::new());
+    }
+    Ok(matcher
+        .find_iter(&rope, 0.into()..rope.len_bytes().into())
+        .filter(|r| !r.is_empty())
+        .map(|r| {
+            let line = rope.byte_to_line(r.start.index);
+            let text = rope.line(line).to_string();
+            let replaced = replacement.map(|replacement| {
+                let line_start = rope.line_to_byte(line);
+                let start = r.start.index - line_start;
+                let end = (r.end.index - line_start).min(text.len());
+                let expanded = matcher.expand(&rope, r.clone(), replacement);
+                preview(&format!("{}{}{}", &text[..start], expanded, &text[end..]))
+            });
+            LineMatch {
+                line,
+                range: r.start.index..r.end.index,
+                preview: preview(&text),
+                replaced,
+            }
+        })
+        .collect())
+}
+
+/// Replace the matches at `ranges` in a file that is not opened in the editor,
+/// saving it back with its original encoding, bom and line feed
+pub fn replace_in_file(
+    path: &Path,
+    matcher: &Matcher,
+    replacement: &str,
+    ranges: &[Range<usize>],
+) -> anyhow::Result<usize> {
+    let (mut info, rope) = TextFileInfo::load(path)?;
+    let mut buffer = Buffer::from_rope(rope, info.indentation.visible_len());
+    let ranges: Vec<_> = ranges
+        .iter()
+        .map(|r| Absolute::from(r.start)..Absolute::from(r.end))
+        .collect();
+    let count = buffer.replace_matches(matcher, replacement, &ranges);
+    if count > 0 {
+        info.save_as(&buffer, path)?;
+    }
+    Ok(count)
+}
+
+/// Walk `root` in a background thread, respecting the .gitignore files, and send the matches file by file to `target`
+fn spawn_search(
+    root: PathBuf,
+    matcher: Matcher,
+    replacement: Option<String>,
+    generation: u64,
+    cancel: Arc<AtomicBool>,
+    event_sink: ExtEventSink,
+    target: WidgetId,
+) {
+    thread::spawn(move || {
+        let mut files = 0;
+        let mut results = 0;
+        for entry in WalkBuilder::new(&root).build().flatten() {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            if results >= MAX_RESULTS {
+                break;
+            }
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            files += 1;
+            match search_file(entry.path(), &matcher, replacement.as_deref()) {
+                Ok(matches) if !matches.is_empty() => {
+                    results += matches.len();
+                    let path = entry.into_path();
+                    let _ =
+                        event_sink.submit_command(FILE_MATCHES, (generation, FileMatches { path, matches }), target);
+                }
+                Ok(_) => (),
+                Err(e) => tracing::warn!("Can't search in {}: {}", entry.path().display(), e),
+            }
+        }
+        let _ = event_sink.submit_command(SEARCH_DONE, (generation, files), target);
+    });
+}
+
+#[derive(Debug, Clone, Data, Lens)]
+pub struct ResultItem {
+    index: usize,
+    #[data(eq)]
+    path: PathBuf,
+    /// `None` for the file header
+    line: Option<usize>,
+    #[data(eq)]
+    range: Range<usize>,
+    text: String,
+    checked: bool,
+}
+
+impl ResultItem {
+    fn open(&self, ctx: &mut EventCtx) {
+        if let Some(line) = self.line {
+            ctx.submit_command(editor_view::OPEN_FILE_AT.with((self.path.clone(), line, self.range.clone())));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Data, Lens, Default)]
+pub struct FindInFilesState {
+    pattern: String,
+    replacement: String,
+    pub folder: String,
+    regex: bool,
+    case_sensitive: bool,
+    whole_word: bool,
+    status: String,
+    file_count: usize,
+    selected: Option<usize>,
+    results: Vector<ResultItem>,
+}
+
+impl FindInFilesState {
+    fn query(&self) -> SearchQuery {
+        SearchQuery {
+            pattern: self.pattern.clone(),
+            regex: self.regex,
+            case_sensitive: self.case_sensitive,
+            whole_word: self.whole_word,
+            wrap_around: false,
+        }
+    }
+
+    fn match_count(&self) -> usize {
+        self.results.len() - self.file_count
+    }
+
+    fn add_file_matches(&mut self, file_matches: FileMatches) {
+        let root = Path::new(&self.folder);
+        let header = file_matches.path.strip_prefix(root).unwrap_or(&file_matches.path);
+        self.results.push_back(ResultItem {
+            index: self.results.len(),
+            path: file_matches.path.clone(),
+            line: None,
+            range: 0..0,
+            text: header.display().to_string(),
+            checked: false,
+        });
+        for m in file_matches.matches {
+            self.results.push_back(ResultItem {
+                index: self.results.len(),
+                path: file_matches.path.clone(),
+                line: Some(m.line),
+                range: m.range,
+                text: match m.replaced {
+                    Some(replaced) => format!("{}: {}  \u{2192}  {}", m.line + 1, m.preview, replaced),
+                    None => format!("    {}: {}", m.line + 1, m.preview),
+                },
+                checked: true,
+            });
+        }
+        self.file_count += 1;
+    }
+
+    /// The checked matches, grouped by file
+    fn checked_matches(&self) -> Vec<(PathBuf, Vec<Range<usize>>)> {
+        let mut files: Vec<(PathBuf, Vec<Range<usize>>)> = Vec::new();
+        for item in self.results.iter().filter(|i| i.line.is_some() && i.checked) {
+            match files.last_mut() {
+                Some((path, ranges)) if *path == item.path => ranges.push(item.range.clone()),
+                _ => files.push((item.path.clone(), vec![item.range.clone()])),
+            }
+        }
+        files
+    }
+}
+
+/// Start the searches and collect their results
+#[derive(Default)]
+struct FindInFiles {
+    generation: u64,
+    cancel: Arc<AtomicBool>,
+    /// The matcher and replacement of the last replace preview
+    preview: Option<(Matcher, String)>,
+}
+
+impl Drop for FindInFiles {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<W: Widget<FindInFilesState>> Controller<FindInFilesState, W> for FindInFiles {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut FindInFilesState, env: &Env) {
+        match event {
+            Event::Command(cmd) if cmd.is(START_SEARCH) => {
+                let with_replacement = *cmd.get_unchecked(START_SEARCH);
+                self.cancel.store(true, Ordering::Relaxed);
+                self.preview = None;
+                data.results.clear();
+                data.selected = None;
+                data.file_count = 0;
+                if data.pattern.is_empty() {
+                    data.status.clear();
+                    return;
+                }
+                match Matcher::new(&data.query()) {
+                    Ok(matcher) => {
+                        self.generation += 1;
+                        self.cancel = Arc::new(AtomicBool::new(false));
+                        data.status = "Searching...".to_owned();
+                        let replacement = if with_replacement {
+                            self.preview = Some((matcher.clone(), data.replacement.clone()));
+                            Some(data.replacement.clone())
+                        } else {
+                            None
+                        };
+                        spawn_search(
+                            PathBuf::from(&data.folder),
+                            matcher,
+                            replacement,
+                            self.generation,
+                            self.cancel.clone(),
+                            ctx.get_external_handle(),
+                            ctx.widget_id(),
+                        );
+                    }
+                    Err(e) => data.status = format!("Invalid search pattern: {}", e),
+                }
+                return;
+            }
+            Event::Command(cmd) if cmd.is(APPLY_REPLACE) => {
+                match self.preview.take() {
+                    Some((matcher, replacement)) => {
+                        self.cancel.store(true, Ordering::Relaxed);
+                        ctx.submit_command(editor_view::REPLACE_IN_FILES.with(ReplaceInFiles {
+                            matcher,
+                            replacement,
+                            files: data.checked_matches(),
+                        }));
+                        data.results.clear();
+                        data.selected = None;
+                        data.file_count = 0;
+                        data.status.clear();
+                    }
+                    None => data.status = "Preview the replacements first".to_owned(),
+                }
+                return;
+            }
+            Event::Command(cmd) if cmd.is(FILE_MATCHES) => {
+                let (generation, file_matches) = cmd.get_unchecked(FILE_MATCHES);
+                if *generation == self.generation {
+                    data.add_file_matches(file_matches.clone());
+                    data.status = format!("Searching... {} results", data.match_count());
+                }
+                return;
+            }
+            Event::Command(cmd) if cmd.is(SEARCH_DONE) => {
+                let (generation, files) = *cmd.get_unchecked(SEARCH_DONE);
+                if generation == self.generation {
+                    data.status = format!(
+                        "{} results in {} of {} files{}",
+                        data.match_count(),
+                        data.file_count,
+                        files,
+                        if data.match_count() >= MAX_RESULTS {
+                            " (truncated)"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+                return;
+            }
+            _ => (),
+        }
+        child.event(ctx, event, data, env)
+    }
+}
+
+/// Keyboard navigation in the results
+struct ResultList;
+
+impl<W: Widget<FindInFilesState>> Controller<FindInFilesState, W> for ResultList {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut FindInFilesState, env: &Env) {
+        match event {
+            Event::MouseDown(_) => ctx.request_focus(),
+            Event::KeyDown(KeyEvent { key, .. }) if ctx.has_focus() && !data.results.is_empty() => {
+                let last = data.results.len() - 1;
+                match key {
+                    KbKey::ArrowDown => data.selected = Some(data.selected.map_or(0, |s| (s + 1).min(last))),
+                    KbKey::ArrowUp => data.selected = Some(data.selected.map_or(0, |s| s.saturating_sub(1))),
+                    KbKey::Enter => {
+                        if let Some(item) = data.selected.and_then(|s| data.results.get(s)) {
+                            item.open(ctx);
+                        }
+                    }
+                    _ => return child.event(ctx, event, data, env),
+                }
+                ctx.set_handled();
+                return;
+            }
+            _ => (),
+        }
+        child.event(ctx, event, data, env)
+    }
+}
+
+fn build_result_item() -> impl Widget<(Option<usize>, ResultItem)> {
+    let text =
+        || Label::new(|(_, item): &(Option<usize>, ResultItem), _env: &Env| item.text.clone()).with_text_size(12.0);
+    Either::new(
+        |(_, item): &(Option<usize>, ResultItem), _env| item.line.is_some(),
+        Flex::row()
+            .with_child(Checkbox::new("").lens(druid::lens!((Option<usize>, ResultItem), 1).then(ResultItem::checked)))
+            .with_child(text()),
+        text(),
+    )
+    .expand_width()
+    .background(Painter::new(
+        |ctx, (selected, item): &(Option<usize>, ResultItem), env| {
+            if *selected == Some(item.index) {
+                let rect = ctx.size().to_rect();
+                ctx.fill(rect, &env.get(crate::theme::SELECTION_BACKGROUND));
+            }
+        },
+    ))
+    .on_click(|ctx, (selected, item): &mut (Option<usize>, ResultItem), _| {
+        *selected = Some(item.index);
+        item.open(ctx);
+    })
+}
+
+pub fn build_panel() -> impl Widget<FindInFilesState> {
+    let results = lens::Map::new(
+        |s: &FindInFilesState| (s.selected, s.results.clone()),
+        |s: &mut FindInFilesState, (selected, results)| {
+            s.selected = selected;
+            s.results = results;
+        },
+    );
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .with_child(panel_label("Find"))
+                .with_flex_child(
+                    TextBox::new()
+                        .with_text_size(12.0)
+                        .focus()
+                        .on_data_received(|ctx, state: &mut String, data: &String, _| {
+                            ctx.request_focus();
+                            state.clone_from(data);
+                        })
+                        .lens(FindInFilesState::pattern)
+                        .on_enter(|ctx, _, _| ctx.submit_command(START_SEARCH.with(false)))
+                        .expand_width(),
+                    1.0,
+                )
+                .with_child(option_checkbox("Match case").lens(FindInFilesState::case_sensitive))
+                .with_child(option_checkbox("Whole word").lens(FindInFilesState::whole_word))
+                .with_child(option_checkbox("Regex").lens(FindInFilesState::regex)),
+        )
+        .with_spacer(2.0)
+        .with_child(
+            Flex::row()
+                .with_child(panel_label("Folder"))
+                .with_flex_child(
+                    TextBox::new()
+                        .with_text_size(12.0)
+                        .lens(FindInFilesState::folder)
+                        .on_enter(|ctx, _, _| ctx.submit_command(START_SEARCH.with(false)))
+                        .expand_width(),
+                    1.0,
+                )
+                .with_child(Button::new("Find").on_click(|ctx, _, _| ctx.submit_command(START_SEARCH.with(false))))
+                .with_child(
+                    Label::new(|data: &FindInFilesState, _env: &Env| data.status.clone())
+                        .with_text_size(12.0)
+                        .fix_width(250.0),
+                ),
+        )
+        .with_spacer(2.0)
+        .with_child(
+            Flex::row()
+                .with_child(panel_label("Replace"))
+                .with_flex_child(
+                    TextBox::new()
+                        .with_text_size(12.0)
+                        .lens(FindInFilesState::replacement)
+                        .on_enter(|ctx, _, _| ctx.submit_command(START_SEARCH.with(true)))
+                        .expand_width(),
+                    1.0,
+                )
+                .with_child(Button::new("Preview").on_click(|ctx, _, _| ctx.submit_command(START_SEARCH.with(true))))
+                .with_child(Button::new("Replace checked").on_click(|ctx, _, _| ctx.submit_command(APPLY_REPLACE))),
+        )
+        .with_spacer(2.0)
+        .with_child(
+            Scroll::new(List::new(build_result_item).lens(results))
+                .vertical()
+                .controller(ResultList)
+                .fix_height(200.0),
+        )
+        .controller(FindInFiles::default())
+}
@@ -3,6 +3,7 @@ mod extension;
 mod palette_view;
 pub mod editor_view;
 pub mod bottom_panel;
+pub mod find_in_files;
 pub mod window;
 pub mod text_buffer;
 
@@ -0,0 +1,109 @@
+use super::buffer::Buffer;
+use super::position::{Line, Relative};
+use super::rope_utils;
+
+impl Buffer {
+    /// The display column of the byte `offset` of `line`, with the tabs expanded
+    fn display_column(&self, line: Line, offset: usize) -> usize {
+        rope_utils::relative_to_column(Relative::from(offset), line, self).index
+    }
+
+    /// Insert spaces before the carets so they are all at the column of the rightmost one.
+    /// With several carets on a line, the first carets of the lines are aligned, then the second ones, and so on.
+    pub fn align_carets(&mut self) {
+        let mut order: Vec<usize> = (0..self.carets.len()).collect();
+        order.sort_by_key(|i| self.carets[*i].start());
+        // the rank of each caret on its line
+        let mut ranks = vec![0; order.len()];
+        for w in 1..order.len() {
+            if self.carets[order[w]].start_line(self) == self.carets[order[w - 1]].start_line(self) {
+                ranks[order[w]] = ranks[order[w - 1]] + 1;
+            }
+        }
+        let max_rank = ranks.iter().copied().max().unwrap_or(0);
+        for rank in 0..=max_rank {
+            let carets: Vec<usize> = order.iter().copied().filter(|i| ranks[*i] == rank).collect();
+            let columns: Vec<usize> = carets
+                .iter()
+                .map(|i| {
+                    let line = self.carets[*i].start_line(self);
+                    self.display_column(line, self.carets[*i].start().index - line.start(self).index)
+                })
+                .collect();
+            let target = columns.iter().copied().max().unwrap_or(0);
+            for (i, column) in carets.into_iter().zip(columns).rev() {
+                if column == target {
+                    continue;
+                }
+                let padding = " ".repeat(target - column);
+                let r = self.carets[i].range();
+                let backward = !self.carets[i].selection_is_empty() && self.carets[i].index == r.start;
+                self.edit(&(r.start..r.start), &padding);
+                let (anchor, head) = (r.start + padding.len(), r.end + padding.len());
+                let (anchor, head) = if backward { (head, anchor) } else { (anchor, head) };
+                self.set_caret_index(i, anchor, true, true);
+                self.set_caret_index(i, head, false, true);
+            }
+        }
+        self.carets.merge();
+    }
+
+    /// Pad the text before the first `delimiter` of each line of the selections, so the delimiters are
+    /// in the same column. The spaces already before the delimiters are replaced by the padding.
+    /// Returns the count of aligned lines.
+    pub fn align_on(&mut self, delimiter: &str) -> usize {
+        let mut count = 0;
+        for block in self.caret_line_blocks().into_iter().rev() {
+            // the line, the end of the text before the delimiter and the delimiter offset
+            let found: Vec<(Line, usize, usize)> = block
+                .map(Line::from)
+                .filter_map(|line| {
+                    let content = self.line_content(line.index);
+                    let at = content.find(delimiter)?;
+                    let key_end = content[..at].trim_end().len();
+                    Some((line, key_end, at))
+                })
+                .collect();
+            if found.len() < 2 {
+                continue;
+            }
+            let gap = usize::from(found.iter().any(|(_, key_end, at)| key_end < at));
+            let target = found
+                .iter()
+                .map(|(line, key_end, _)| self.display_column(*line, *key_end))
+                .max()
+                .unwrap_or(0)
+                + gap;
+            for (line, key_end, at) in found.into_iter().rev() {
+                let column = self.display_column(line, key_end);
+                let start = line.start(self);
+                self.edit(&(start + key_end..start + at), &" ".repeat(target - column));
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::widgets::text_buffer::position::{Absolute, Column};
+
+    #[test]
+    fn align() {
+        let mut b = Buffer::with_caret("a = 1\n\tlong  = 2\nnone\nbc= 3", 0..27);
+        assert_eq!(b.align_on("="), 3);
+        assert_eq!(b.rope.to_string(), "a        = 1\n\tlong = 2\nnone\nbc       = 3");
+
+        let mut b = Buffer::from_rope("x\n\tyy\nzzz".into(), 4);
+        b.select_block((Line::from(0), Column::from(1)), (Line::from(2), Column::from(1)));
+        let snapshot = b.clone();
+        let positions: Vec<Absolute> = vec![1.into(), 5.into(), 9.into()];
+        for (c, p) in b.carets.iter_mut().zip(positions) {
+            c.set_index(p, true, true, &snapshot);
+        }
+        b.align_carets();
+        assert_eq!(b.rope.to_string(), "x     \n\tyy\nzzz   ");
+    }
+}
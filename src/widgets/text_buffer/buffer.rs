@@ -1,8 +1,12 @@
 use super::{
     caret::{Caret, Carets},
     file::{Indentation, LineFeed},
+    pairs::Pair,
     position::{Absolute, Column, Line, Point, Position, Relative},
-    rope_utils, SelectionLineRange,
+    rope_utils,
+    search::{Matcher, SearchQuery},
+    undo::EditOp,
+    SelectionLineRange,
 };
 use druid::Data;
 use ropey::{Rope, RopeSlice};
@@ -19,6 +23,10 @@ pub struct Buffer {
     pub(super) tabsize: usize,
     uuid: Uuid,
     max_visible_line_grapheme_len: Cell<usize>,
+    /// The edits not yet recorded in the undo history
+    journal: Vec<EditOp>,
+    /// The pairs auto closed by `type_char` a caret is still in, from the opening char to the closing one
+    pub(super) auto_closed: Vec<Range<Absolute>>,
 }
 
 impl Data for Buffer {
@@ -41,6 +49,8 @@ impl Buffer {
             uuid: Uuid::new_v4(),
             tabsize,
             max_visible_line_grapheme_len: Cell::new(0),
+            journal: Vec::new(),
+            auto_closed: Vec::new(),
         }
     }
 
@@ -55,6 +65,8 @@ impl Buffer {
             uuid: Uuid::new_v4(),
             tabsize,
             max_visible_line_grapheme_len: Cell::new(0),
+            journal: Vec::new(),
+            auto_closed: Vec::new(),
         };
         for line in 0..100.min(b.len_lines()) {
             let l = b
@@ -67,6 +79,16 @@ impl Buffer {
         b
     }
 
+    /// A buffer of `text` with the main caret selecting `selection`, an empty range for a plain caret
+    #[cfg(test)]
+    pub(super) fn with_caret(text: &str, selection: Range<usize>) -> Self {
+        let mut b = Self::from_rope(text.into(), 4);
+        let snapshot = b.clone();
+        b.carets[0].set_index(Absolute::from(selection.start), true, true, &snapshot);
+        b.carets[0].set_index(Absolute::from(selection.end), false, true, &snapshot);
+        b
+    }
+
     /// Construct a string with tab replaced as space
     pub fn displayable_line(
         &self,
@@ -194,6 +216,7 @@ impl Buffer {
         }
 
         self.carets.merge();
+        self.forget_left_pairs();
     }
 
     pub fn forward(&mut self, expand_selection: bool, word_boundary: bool) {
@@ -203,6 +226,7 @@ impl Buffer {
         }
 
         self.carets.merge();
+        self.forget_left_pairs();
     }
 
     pub fn up(&mut self, expand_selection: bool) {
@@ -212,6 +236,7 @@ impl Buffer {
         }
 
         self.carets.merge();
+        self.forget_left_pairs();
     }
     pub fn down(&mut self, expand_selection: bool) {
         let b = self.clone();
@@ -220,6 +245,7 @@ impl Buffer {
         }
 
         self.carets.merge();
+        self.forget_left_pairs();
     }
     pub fn duplicate_down(&mut self) {
         self.carets.sort_unstable();
@@ -250,6 +276,7 @@ impl Buffer {
         }
 
         self.carets.merge();
+        self.forget_left_pairs();
     }
 
     pub fn home(&mut self, expand_selection: bool) {
@@ -258,36 +285,44 @@ impl Buffer {
             s.move_home(expand_selection, &b);
         }
         self.carets.merge();
+        self.forget_left_pairs();
     }
 
     pub fn insert(&mut self, text: &str, expand_selection: bool) {
         for i in 0..self.carets.len() {
+            self.fill_virtual_space(i);
             let r = self.carets[i].range();
             self.edit(&r, text);
-            let b = self.clone();
-            self.carets[i].set_index(r.start + text.len(), !expand_selection, true, &b);
+            self.set_caret_index(i, r.start + text.len(), !expand_selection, true);
         }
         self.carets.merge();
     }
 
-    pub fn backspace(&mut self) -> bool {
+    /// Delete the selections, or the grapheme before each caret, or both chars of an empty pair of `pairs`.
+    /// In the leading spaces of a line, a whole `indentation` unit is deleted.
+    pub fn backspace(&mut self, pairs: &[Pair], indentation: Indentation) -> bool {
         let mut did_nothing = true;
         for i in 0..self.carets.len() {
-            if !self.carets[i].selection_is_empty() {
+            if self.carets[i].collapse_virtual_selection() || self.carets[i].backward_in_virtual_space() {
+                did_nothing = false;
+            } else if !self.carets[i].selection_is_empty() {
                 // delete all the selection
                 let r = self.carets[i].range();
                 self.edit(&r, "");
-                let b = self.clone();
-                self.carets[i].set_index(r.start, true, true, &b);
+                self.set_caret_index(i, r.start, true, true);
 
                 did_nothing = false;
             } else if self.carets[i].index > 0.into() {
-                // delete the preceding grapheme
-                let r = rope_utils::prev_grapheme_boundary(&self.rope.slice(..), self.carets[i].index).into()
-                    ..self.carets[i].index;
+                // delete the preceding grapheme, the empty pair around the caret, or an indentation unit
+                let r = self
+                    .empty_pair_around(i, pairs)
+                    .or_else(|| self.indentation_before(i, indentation))
+                    .unwrap_or_else(|| {
+                        rope_utils::prev_grapheme_boundary(&self.rope.slice(..), self.carets[i].index).into()
+                            ..self.carets[i].index
+                    });
                 self.edit(&r, "");
-                let b = self.clone();
-                self.carets[i].set_index(r.start, true, true, &b);
+                self.set_caret_index(i, r.start, true, true);
 
                 did_nothing = false;
             } else {
@@ -304,19 +339,22 @@ impl Buffer {
     pub fn delete(&mut self) -> bool {
         let mut did_nothing = true;
         for i in 0..self.carets.len() {
+            if self.carets[i].collapse_virtual_selection() {
+                did_nothing = false;
+                continue;
+            }
+            self.fill_virtual_space(i);
             if !self.carets[i].selection_is_empty() {
                 let r = self.carets[i].range();
                 self.edit(&r, "");
-                let b = self.clone();
-                self.carets[i].set_index(r.start, true, true, &b);
+                self.set_caret_index(i, r.start, true, true);
 
                 did_nothing = false;
             } else if self.carets[i].index < self.rope.len_bytes().into() {
                 let r = self.carets[i].index
                     ..rope_utils::next_grapheme_boundary(&self.rope.slice(..), self.carets[i].index).into();
                 self.edit(&r, "");
-                let b = self.clone();
-                self.carets[i].set_index(r.start, true, true, &b);
+                self.set_caret_index(i, r.start, true, true);
 
                 did_nothing = false;
             } else {
@@ -330,83 +368,214 @@ impl Buffer {
         false
     }
 
+    /// Insert indentation at each caret, or indent the selected lines if a selection spans several lines
     pub fn tab(&mut self, indentation: Indentation) {
+        if self.carets.iter().any(|c| c.start_line(self) != c.end_line(self)) {
+            self.indent_lines(indentation);
+            return;
+        }
         for i in 0..self.carets.len() {
-            match self.carets[i].selected_lines_range(&self) {
-                Some(line_range) if line_range.start() != line_range.end() => {
-                    // TODO: Find a better way to iterate over line of a selection
-                    for line_idx in line_range.start().index..line_range.end().index + 1 {
-                        let line_start: Absolute = self.rope.line_to_byte(line_idx).into();
-                        let r = line_start..line_start;
-                        let text = match indentation {
-                            Indentation::Space(n) => " ".repeat(n).to_owned(),
-                            Indentation::Tab(_) => "\t".to_owned(),
-                        };
-                        self.edit(&r, &text);
-                    }
-                }
-                _ => {
-                    let r = self.carets[i].range();
-                    let text = match indentation {
-                        Indentation::Space(n) => {
-                            let start: usize = self.carets[i].col().into();
-                            let nb_space = n - start % n;
-                            " ".repeat(nb_space).to_owned()
-                        }
-                        Indentation::Tab(_) => "\t".to_owned(),
-                    };
-                    self.edit(&r, &text);
-                    let b = self.clone();
-                    self.carets[i].set_index(r.start + Relative::from(text.len()), true, true, &b);
+            self.fill_virtual_space(i);
+            let r = self.carets[i].range();
+            let text = match indentation {
+                Indentation::Space(n) => {
+                    let start: usize = self.carets[i].col().into();
+                    let nb_space = n - start % n;
+                    " ".repeat(nb_space)
                 }
-            }
+                Indentation::Tab(_) => "\t".to_owned(),
+            };
+            self.edit(&r, &text);
+            self.set_caret_index(i, r.start + Relative::from(text.len()), true, true);
         }
         self.carets.merge();
     }
 
-    pub fn indent(&mut self, indentation: Indentation) {
-        if self.have_selection() {
-            return;
-        }
-        for i in 0..self.carets.len() {
-            match self.carets[i].index.line(&self).index {
-                0 => (),
-                max if max == self.len_lines() => (),
-                l => {
-                    let l = Line::from(l);
-                    let indent = l.prev().unwrap().indentation(&self);
-                    let text = match indentation {
-                        Indentation::Space(_) => {
-                            " ".repeat(indent.into()).to_owned()
-                        }
-                        Indentation::Tab(_) =>  "\t".repeat(indent.index / indentation.visible_len()).to_owned(),
-                    };
-                    self.edit(&Range{start: l.start(&self),end: l.start(&self)}, &text );
-                    let b = self.clone();
-                    self.carets[i].set_index(l.start(&b) + Relative::from(text.len()), true, true, &b);
+    /// Add one `indentation` unit at the start of the non empty lines of the carets
+    pub fn indent_lines(&mut self, indentation: Indentation) {
+        let text = match indentation {
+            Indentation::Space(n) => " ".repeat(n),
+            Indentation::Tab(_) => "\t".to_owned(),
+        };
+        for block in self.caret_line_blocks().into_iter().rev() {
+            for line in block.rev().map(Line::from) {
+                let start = line.start(self);
+                if start < line.end(self) {
+                    self.edit(&(start..start), &text);
                 }
+            }
+        }
+    }
 
+    /// Remove one `indentation` unit from the indentation of the lines of the carets.
+    /// A leading tab is a whole unit, whatever the indentation of the file.
+    pub fn outdent_lines(&mut self, indentation: Indentation) {
+        for block in self.caret_line_blocks().into_iter().rev() {
+            for line in block.rev().map(Line::from) {
+                let start = line.start(self);
+                let len = match self.slice(start..line.absolute_indentation(self)).bytes().next() {
+                    Some(b'\t') => 1,
+                    Some(_) => self
+                        .slice(start..line.absolute_indentation(self))
+                        .bytes()
+                        .take_while(|b| *b == b' ')
+                        .take(indentation.visible_len())
+                        .count(),
+                    None => 0,
+                };
+                if len > 0 {
+                    self.edit(&(start..start + len), "");
+                }
             }
         }
     }
 
+    /// The spaces to delete before the caret `i` to go back to the previous indentation stop,
+    /// if the caret is in the leading spaces of its line
+    fn indentation_before(&self, i: usize, indentation: Indentation) -> Option<Range<Absolute>> {
+        let index = self.carets[i].index;
+        let line = self.carets[i].line();
+        let start = line.start(self);
+        if index <= start || index > line.absolute_indentation(self) {
+            return None;
+        }
+        let unit = indentation.len_as_byte();
+        let col: usize = self.carets[i].col().into();
+        let len = (col - 1) % unit + 1;
+        (len > 1 && index.index - start.index >= len && self.slice(index - len..index).bytes().all(|b| b == b' '))
+            .then_some(index - len..index)
+    }
+
     pub fn edit(&mut self, range: &Range<Absolute>, text: &str) {
         let insert_index = self.rope.byte_to_char(range.start.into());
         let end_index = self.rope.byte_to_char(range.end.into());
         let cr = insert_index..end_index;
+        self.journal.push(EditOp {
+            start: range.start,
+            removed: self.rope.slice(cr.clone()).to_string(),
+            inserted: text.to_owned(),
+        });
         self.rope.remove(cr);
         self.rope.insert(insert_index, text);
+        self.update_auto_closed(range, text.len());
 
-        for i in 0..self.carets.len() {
-            let b = self.clone();
-            self.carets[i].update_after_delete(range.start, range.end - range.start, &b); // TODO verify this
-            let b = self.clone();
-            self.carets[i].update_after_insert(range.start, text.len().into(), &b);
+        let mut carets = std::mem::take(&mut self.carets);
+        for c in carets.iter_mut() {
+            c.update_after_delete(range.start, range.end - range.start, self); // TODO verify this
+            c.update_after_insert(range.start, text.len().into(), self);
         }
-        self.carets.merge();
+        carets.merge();
+        self.carets = carets;
         self.uuid = Uuid::new_v4();
     }
 
+    /// Replace the virtual space before the start of the caret `i` by spaces, so text can be inserted there
+    pub(super) fn fill_virtual_space(&mut self, i: usize) {
+        let pad = self.carets[i].start_virtual_space();
+        if pad > 0 {
+            let at = self.carets[i].start();
+            self.edit(&(at..at), &" ".repeat(pad));
+            self.set_caret_index(i, at + pad, true, true);
+        } else {
+            self.carets[i].clear_virtual_space();
+        }
+    }
+
+    /// Select the rectangle between `anchor` and `head`, given as line and visual column, with a caret on each line
+    pub fn select_block(&mut self, anchor: (Line, Column), head: (Line, Column)) {
+        self.carets = Carets::block(anchor, head, self);
+    }
+
+    /// Grow or shrink the block selection by moving its head, the main caret, by `lines` and `cols`.
+    /// A caret with no block selection starts one from its selection.
+    pub fn move_block_head(&mut self, lines: isize, cols: isize) {
+        let main = self.main_caret();
+        let head = (main.line(), main.visual_col());
+        let anchor_col = main.selection_visual_col(self);
+        let anchor_line = if self.carets.is_block(self) {
+            let first = self.first_caret().line();
+            let last = self.carets.iter().map(|c| c.line()).max().unwrap_or(first);
+            if head.0 == first {
+                last
+            } else {
+                first
+            }
+        } else if main.index == main.start() {
+            main.end().line(self)
+        } else {
+            main.start().line(self)
+        };
+        let line = (head.0.index as isize + lines).clamp(0, self.len_lines() as isize - 1) as usize;
+        let col = (head.1.index as isize + cols).max(0) as usize;
+        self.select_block((anchor_line, anchor_col), (Line::from(line), Column::from(col)));
+    }
+
+    pub fn is_block_selection(&self) -> bool {
+        self.carets.is_block(self)
+    }
+
+    /// The text of each line of a block selection, without trailing line feed
+    pub fn block_text(&self, line_feed: LineFeed) -> String {
+        self.carets
+            .iter()
+            .map(|c| self.slice(c.range()).to_string())
+            .collect::<Vec<_>>()
+            .join(line_feed.to_str())
+    }
+
+    /// Insert the lines of a copied block at the same visual column on consecutive lines, starting at the main caret.
+    /// Short lines are padded with spaces and missing lines are added at the end of the buffer.
+    /// With one caret per block line, each line replace the selection of its caret.
+    pub fn insert_block(&mut self, lines: &[&str], line_feed: LineFeed) {
+        if lines.len() > 1 && lines.len() == self.carets.len() {
+            for (i, text) in lines.iter().enumerate() {
+                self.fill_virtual_space(i);
+                let r = self.carets[i].range();
+                self.edit(&r, text);
+                self.set_caret_index(i, r.start + text.len(), true, true);
+            }
+            self.carets.merge();
+            return;
+        }
+
+        self.cancel_mutli_carets();
+        self.fill_virtual_space(0);
+        let r = self.carets[0].range();
+        self.edit(&r, "");
+        self.set_caret_index(0, r.start, true, true);
+        let first_line = self.carets[0].line().index;
+        let col = self.carets[0].col();
+        let mut end = r.start;
+        for (i, text) in lines.iter().enumerate() {
+            let line = Line::from(first_line + i);
+            if line.index >= self.len_lines() {
+                let len = self.len();
+                self.edit(&(len..len), line_feed.to_str());
+            }
+            let end_col = line.end(self).point(self).col;
+            if end_col < col {
+                let at = line.end(self);
+                self.edit(&(at..at), &" ".repeat(col.index - end_col.index));
+            }
+            let at = Point::new(col, line, self).absolute(self);
+            self.edit(&(at..at), text);
+            end = at + text.len();
+        }
+        self.set_caret_index(0, end, true, true);
+    }
+
+    /// Move the caret `i` without cloning the whole buffer
+    pub(super) fn set_caret_index(&mut self, i: usize, index: Absolute, reset_selection: bool, reset_sticky_col: bool) {
+        let mut carets = std::mem::take(&mut self.carets);
+        carets[i].set_index(index, reset_selection, reset_sticky_col, self);
+        self.carets = carets;
+    }
+
+    /// Take the edits recorded since the last call
+    pub(super) fn take_journal(&mut self) -> Vec<EditOp> {
+        std::mem::take(&mut self.journal)
+    }
+
     pub fn has_many_carets(&self) -> bool {
         self.carets.len() > 1
     }
@@ -427,46 +596,170 @@ impl Buffer {
             .slice(self.rope.byte_to_char(start.index)..self.rope.byte_to_char(end.index))
     }
 
-    fn search_next_in_range(&self, s: &str, r: Range<Absolute>) -> Option<Absolute> {
-        let mut index = r.start;
-        let slice = self.slice(r);
-        let s = s.to_lowercase();
-        slice.lines().find_map(|l| match l.to_string().to_lowercase().find(&s) {
-            Some(i) => Some(index + i),
-            None => {
-                index += l.len_bytes();
-                None
-            }
-        })
+    /// Select the next match of `matcher` after the main caret
+    pub fn search_next(&mut self, matcher: &Matcher, wrap_around: bool) -> bool {
+        let start_index = self.main_caret().end();
+        let mut m = self.next_match(matcher, start_index..self.len());
+        if m.is_none() && wrap_around {
+            m = self.next_match(matcher, 0.into()..start_index);
+        }
+        self.select_match(m)
     }
 
-    pub fn search_next(&mut self, s: &str) {
-        let start_index = self.main_caret().index;
+    /// Select the previous match of `matcher` before the main caret
+    pub fn search_prev(&mut self, matcher: &Matcher, wrap_around: bool) -> bool {
+        let start_index = self.main_caret().start();
+        let mut m = matcher.rfind(&self.rope, 0.into()..start_index);
+        if m.is_none() && wrap_around {
+            m = matcher.rfind(&self.rope, self.main_caret().end()..self.len());
+        }
+        self.select_match(m)
+    }
 
-        let i = self
-            .search_next_in_range(s, start_index..self.len())
-            .or_else(|| self.search_next_in_range(s, 0.into()..start_index));
-        if let Some(i) = i {
+    fn select_match(&mut self, m: Option<Range<Absolute>>) -> bool {
+        if let Some(m) = m {
             self.cancel_mutli_carets();
-            self.move_main_caret_to(i, false, false);
-            self.move_main_caret_to(i + s.len(), true, false);
+            self.move_main_caret_to(m.start, false, false);
+            self.move_main_caret_to(m.end, true, false);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Replace the match selected by the main caret, expanding capture references,
+    /// then select the next match. If the main caret is not on a match, just search the next one.
+    /// Return true if a replacement was made.
+    pub fn replace_next(&mut self, matcher: &Matcher, replacement: &str, wrap_around: bool) -> bool {
+        let r = self.main_caret().range();
+        if r.start == r.end || !matcher.is_match_at(&self.rope, r.clone()) {
+            self.search_next(matcher, wrap_around);
+            return false;
+        }
+        let text = matcher.expand(&self.rope, r.clone(), replacement);
+        self.cancel_mutli_carets();
+        self.edit(&r, &text);
+        self.move_main_caret_to(r.start + text.len(), false, false);
+        self.search_next(matcher, wrap_around);
+        true
+    }
+
+    /// Replace all the matches contained in `range`, return the number of replacements
+    pub fn replace_all(&mut self, matcher: &Matcher, replacement: &str, range: Range<Absolute>) -> usize {
+        let replacements: Vec<_> = matcher
+            .find_iter(&self.rope, range)
+            .map(|m| (m.clone(), matcher.expand(&self.rope, m, replacement)))
+            .collect();
+        // from the end, so the remaining ranges stay valid
+        for (r, text) in replacements.iter().rev() {
+            self.edit(r, text);
+        }
+        replacements.len()
+    }
+
+    /// Replace the given matches, skipping the ranges that are no longer a match of `matcher`.
+    /// Return the number of replacements.
+    pub fn replace_matches(&mut self, matcher: &Matcher, replacement: &str, ranges: &[Range<Absolute>]) -> usize {
+        let mut replacements: Vec<_> = ranges
+            .iter()
+            .filter(|r| r.end <= self.len() && matcher.is_match_at(&self.rope, (*r).clone()))
+            .map(|r| (r.clone(), matcher.expand(&self.rope, r.clone(), replacement)))
+            .collect();
+        replacements.sort_unstable_by_key(|(r, _)| r.start);
+        for (r, text) in replacements.iter().rev() {
+            self.edit(r, text);
         }
+        replacements.len()
+    }
+
+    /// Replace all the matches contained in the carets selections, return the number of replacements
+    pub fn replace_in_selection(&mut self, matcher: &Matcher, replacement: &str) -> usize {
+        let mut ranges: Vec<_> = self
+            .carets
+            .iter()
+            .filter(|c| !c.selection_is_empty())
+            .map(|c| c.range())
+            .collect();
+        ranges.sort_unstable_by_key(|r| r.start);
+        ranges
+            .into_iter()
+            .rev()
+            .map(|r| self.replace_all(matcher, replacement, r))
+            .sum()
+    }
+
+    fn next_match(&self, matcher: &Matcher, r: Range<Absolute>) -> Option<Range<Absolute>> {
+        // skip empty matches at the caret position, or we will never move forward
+        matcher
+            .find_iter(&self.rope, r.start..self.len())
+            .find(|m| m.start != m.end || m.start != r.start)
+            .filter(|m| m.start < r.end)
     }
 
     pub fn duplicate_cursor_from_str(&mut self, s: &str) {
+        let matcher = match Matcher::new(&SearchQuery::literal(s)) {
+            Ok(m) if !s.is_empty() => m,
+            _ => return,
+        };
         let start_index = self.last_created_caret().end();
-        let i = self
-            .search_next_in_range(s, start_index..self.len())
-            .or_else(|| self.search_next_in_range(s, 0.into()..start_index));
-        if let Some(i) = i {
-            if !self.carets.iter().any(|c| c.start() == i) {
+        let m = self
+            .next_match(&matcher, start_index..self.len())
+            .or_else(|| self.next_match(&matcher, 0.into()..start_index));
+        if let Some(m) = m {
+            if !self.carets.iter().any(|c| c.start() == m.start) {
                 self.carets.sort_unstable();
-                let c = self.last_created_caret().duplicate_to(i, i + s.len(), &self);
+                let c = self.last_created_caret().duplicate_to(m.start, m.end, &self);
                 self.carets.push(c);
             }
         }
     }
 
+    /// Add a caret on every occurrence of `s`
+    pub fn select_all_occurrences(&mut self, s: &str) {
+        let matcher = match Matcher::new(&SearchQuery::literal(s)) {
+            Ok(m) if !s.is_empty() => m,
+            _ => return,
+        };
+        let ranges: Vec<_> = matcher.find_iter(&self.rope, 0.into()..self.len()).collect();
+        let mut carets = std::mem::take(&mut self.carets);
+        carets.add_selections(ranges, self);
+        self.carets = carets;
+    }
+
+    /// Remove the last added caret and select the next occurrence of `s` instead
+    pub fn skip_occurrence(&mut self, s: &str) {
+        let matcher = match Matcher::new(&SearchQuery::literal(s)) {
+            Ok(m) if !s.is_empty() => m,
+            _ => return,
+        };
+        let skipped = match self.carets.remove_last_created() {
+            Some(c) => c,
+            // no added caret, move the main one to the next occurrence
+            None => {
+                self.search_next(&matcher, true);
+                return;
+            }
+        };
+        let start_index = skipped.end();
+        let m = self
+            .next_match(&matcher, start_index..self.len())
+            .or_else(|| self.next_match(&matcher, 0.into()..start_index))
+            .filter(|m| *m != skipped.range());
+        let mut carets = std::mem::take(&mut self.carets);
+        carets.add_selections(m, self);
+        self.carets = carets;
+    }
+
+    pub fn undo_last_caret(&mut self) {
+        self.carets.remove_last_created();
+    }
+
+    pub fn add_carets_to_line_ends(&mut self) {
+        let mut carets = std::mem::take(&mut self.carets);
+        carets.add_line_ends(self);
+        self.carets = carets;
+    }
+
     pub fn main_caret(&self) -> &Caret {
         self.carets.iter().find(|c| !c.is_clone).expect("No main cursor found!")
     }
@@ -479,10 +772,7 @@ impl Buffer {
     }
 
     pub fn last_created_caret(&self) -> &Caret {
-        self.carets
-            .iter()
-            .max_by_key(|c| c.generation)
-            .expect("No cursor found!")
+        self.carets.last_created()
     }
 
     pub fn main_caret_mut(&mut self) -> &mut Caret {
@@ -515,6 +805,7 @@ impl Buffer {
         } else {
             self.carets[0].set_index(p, !expand_selection, true, &b);
         }
+        self.forget_left_pairs();
     }
 
     pub fn selected_text(&self, line_feed: LineFeed) -> String {
@@ -611,3 +902,43 @@ pub(super) fn end_bound_to_num(b: Bound<&Absolute>) -> Option<Absolute> {
         Bound::Unbounded => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_selection() {
+        let mut b = Buffer::from_rope("abcdef\nab\n\tx\nabcdefgh".into(), 4);
+        b.select_block((Line::from(0), Column::from(3)), (Line::from(3), Column::from(5)));
+        assert_eq!(b.carets.len(), 4);
+        assert!(b.is_block_selection());
+        assert_eq!(b.block_text(LineFeed::Lf), "de\n\nx\nde");
+        b.insert("Z", false);
+        assert_eq!(b.rope.to_string(), "abcZf\nab Z\n\tZ\nabcZfgh");
+        let mut b = Buffer::from_rope("ab\nc".into(), 4);
+        b.insert_block(&["12", "34", "56"], LineFeed::Lf);
+        assert_eq!(b.rope.to_string(), "12ab\n34c\n56");
+        let mut b = Buffer::from_rope("abcdef\nab".into(), 4);
+        b.select_block((Line::from(0), Column::from(4)), (Line::from(1), Column::from(4)));
+        b.backspace(&[], Indentation::default());
+        b.insert("Z", false);
+        assert_eq!(b.rope.to_string(), "abcZef\nab Z");
+    }
+
+    #[test]
+    fn indent_and_outdent() {
+        let mut b = Buffer::with_caret("a\n\n  b\nc", 0..6);
+        b.tab(Indentation::Space(4));
+        assert_eq!(b.rope.to_string(), "    a\n\n      b\nc");
+        b.outdent_lines(Indentation::Space(4));
+        b.outdent_lines(Indentation::Space(4));
+        assert_eq!(b.rope.to_string(), "a\n\nb\nc");
+
+        let mut b = Buffer::with_caret("      x", 6..6);
+        b.backspace(&[], Indentation::Space(4));
+        assert_eq!(b.rope.to_string(), "    x");
+        b.backspace(&[], Indentation::Space(4));
+        assert_eq!(b.rope.to_string(), "x");
+    }
+}
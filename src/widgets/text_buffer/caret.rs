@@ -36,6 +36,117 @@ impl Carets {
             redo = false;
         }
     }
+
+    pub fn last_created(&self) -> &Caret {
+        self.intern
+            .iter()
+            .max_by_key(|c| c.generation)
+            .expect("No cursor found!")
+    }
+
+    /// Add a caret selecting each range, unless a caret already select it
+    pub fn add_selections<I>(&mut self, ranges: I, buffer: &Buffer)
+    where
+        I: IntoIterator<Item = Range<Absolute>>,
+    {
+        for r in ranges {
+            if !self.intern.iter().any(|c| c.range() == r) {
+                let c = self.last_created().duplicate_to(r.start, r.end, buffer);
+                self.intern.push(c);
+            }
+        }
+        self.merge();
+    }
+
+    /// Remove the most recently created caret. The main caret is never removed.
+    pub fn remove_last_created(&mut self) -> Option<Caret> {
+        let (i, _) = self
+            .intern
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_clone)
+            .max_by_key(|(_, c)| c.generation)?;
+        Some(self.intern.remove(i))
+    }
+
+    /// Replace each selection spanning several lines by a caret at the end of each selected line
+    pub fn add_line_ends(&mut self, buffer: &Buffer) {
+        let mut carets = Vec::new();
+        for c in self.intern.drain(..) {
+            let (first, last) = (c.start_line(buffer), c.end_line(buffer));
+            if first == last {
+                carets.push(c);
+                continue;
+            }
+            // a selection ending at the start of a line doesn't really select it
+            let last = if c.end() == last.start(buffer) { last.index - 1 } else { last.index };
+            for line in first.index..=last {
+                let index = Line::from(line).end(buffer).min(c.end());
+                let mut caret = c.duplicate_to(index, index, buffer);
+                caret.is_clone = c.is_clone || line != first.index;
+                carets.push(caret);
+            }
+        }
+        self.intern = carets;
+        self.merge();
+    }
+
+    /// A caret on each line from `anchor` to `head`, selecting the rectangle between their visual columns.
+    /// The caret on the line of `head` is the main one.
+    pub(super) fn block(anchor: (Line, Column), head: (Line, Column), buffer: &Buffer) -> Self {
+        let first = anchor.0.index.min(head.0.index);
+        let last = anchor.0.index.max(head.0.index).min(buffer.len_lines() - 1);
+        let mut intern: Vec<Caret> = (first..=last)
+            .map(|l| {
+                let mut c = Caret::in_block(Line::from(l), anchor.1, head.1, buffer);
+                c.is_clone = l != head.0.index;
+                c.generation = if c.is_clone { 1 } else { 0 };
+                c
+            })
+            .collect();
+        if intern.iter().all(|c| c.is_clone) {
+            intern[0].is_clone = false;
+        }
+        Self { intern }
+    }
+
+    /// True if there is several carets, on consecutive lines, as made by a block selection
+    pub fn is_block(&self, buffer: &Buffer) -> bool {
+        self.intern.len() > 1
+            && self.intern.windows(2).all(|w| {
+                w[0].start_line(buffer) == w[0].end_line(buffer)
+                    && w[1].start_line(buffer) == w[1].end_line(buffer)
+                    && w[0].start_line(buffer).index + 1 == w[1].start_line(buffer).index
+            })
+    }
+
+    /// The index, selection start and clone flag of each caret
+    pub(super) fn positions(&self) -> Vec<(Absolute, Absolute, bool)> {
+        self.intern.iter().map(|c| (c.index, c.selection, c.is_clone)).collect()
+    }
+
+    /// Rebuild carets saved with `positions`
+    pub(super) fn from_positions(positions: &[(Absolute, Absolute, bool)], buffer: &Buffer) -> Self {
+        let mut intern: Vec<Caret> = positions
+            .iter()
+            .map(|&(index, selection, is_clone)| {
+                let mut c = Caret::new();
+                c.set_index(selection.min(buffer.len()), true, true, buffer);
+                c.set_index(index.min(buffer.len()), false, true, buffer);
+                c.is_clone = is_clone;
+                c
+            })
+            .collect();
+        if !intern.iter().any(|c| !c.is_clone) {
+            match intern.first_mut() {
+                Some(c) => c.is_clone = false,
+                None => intern.push(Caret::new()),
+            }
+        }
+        let mut carets = Self { intern };
+        carets.merge();
+        carets
+    }
 }
 
 impl Default for Carets {
@@ -89,12 +200,18 @@ pub struct Caret {
     sticky_col: Column,
     pub is_clone: bool,
     pub(super) generation: usize,
+    /// Columns between the end of the line and the caret, when a block selection puts it past the end of a short line
+    virtual_space: usize,
+    /// Same as `virtual_space`, for the other end of the selection
+    selection_virtual_space: usize,
 }
 
 impl Data for Caret {
     fn same(&self, other: &Self) -> bool {
         self.index == other.index &&
-        self.selection == other.selection
+        self.selection == other.selection &&
+        self.virtual_space == other.virtual_space &&
+        self.selection_virtual_space == other.selection_virtual_space
     }
 }
 
@@ -107,6 +224,8 @@ impl Clone for Caret {
             sticky_col: self.sticky_col,
             is_clone: true,
             generation: self.generation + 1,
+            virtual_space: self.virtual_space,
+            selection_virtual_space: self.selection_virtual_space,
         }
     }
 }
@@ -132,6 +251,8 @@ impl Caret {
             sticky_col: Default::default(),
             is_clone: false,
             generation: 0,
+            virtual_space: 0,
+            selection_virtual_space: 0,
         }
     }
     fn from_point(p: Point, buffer: &Buffer) -> Self {
@@ -142,6 +263,8 @@ impl Caret {
             sticky_col: p.col,
             is_clone: false,
             generation: 0,
+            virtual_space: 0,
+            selection_virtual_space: 0,
         }
     }
 
@@ -155,6 +278,8 @@ impl Caret {
                 selection: cend.end(),
                 is_clone: cstart.is_clone && cend.is_clone,
                 generation: cstart.generation.max(cend.generation),
+                virtual_space: 0,
+                selection_virtual_space: 0,
             }
         } else {
             let (cstart, cend) = if c1.end() < c2.end() { (c1, c2) } else { (c2, c1) };
@@ -165,6 +290,8 @@ impl Caret {
                 selection: cstart.start(),
                 is_clone: cstart.is_clone && cend.is_clone,
                 generation: cstart.generation.max(cend.generation),
+                virtual_space: 0,
+                selection_virtual_space: 0,
             }
         }
     }
@@ -227,8 +354,10 @@ impl Caret {
 
     pub fn set_index(&mut self, index: Absolute, reset_selection: bool, reset_sticky_col: bool, buffer: &Buffer) {
         self.index = index;
+        self.virtual_space = 0;
         if reset_selection {
             self.selection = index;
+            self.selection_virtual_space = 0;
         }
         self.point = self.index.point(buffer);
         if reset_sticky_col {
@@ -286,6 +415,89 @@ impl Caret {
         }
     }
 
+    /// A caret of a block selection on `line`, selecting from the visual column `anchor` to `head`
+    fn in_block(line: Line, anchor: Column, head: Column, buffer: &Buffer) -> Self {
+        let end_col = line.end(buffer).point(buffer).col;
+        let in_line = |col: Column| {
+            if col > end_col {
+                (line.end(buffer), col.index - end_col.index)
+            } else {
+                (Point::new(col, line, buffer).absolute(buffer), 0)
+            }
+        };
+        let (selection, selection_virtual_space) = in_line(anchor);
+        let (index, virtual_space) = in_line(head);
+        let mut c = Caret::new();
+        c.set_index(selection, true, true, buffer);
+        c.set_index(index, false, true, buffer);
+        c.virtual_space = virtual_space;
+        c.selection_virtual_space = selection_virtual_space;
+        c.sticky_col = head;
+        c
+    }
+
+    /// Columns between the end of the line and the caret
+    pub fn virtual_space(&self) -> usize {
+        self.virtual_space
+    }
+
+    /// The visual column of the caret, counting the virtual space
+    pub fn visual_col(&self) -> Column {
+        Column::from(self.point.col.index + self.virtual_space)
+    }
+
+    /// The visual column of the other end of the selection, counting the virtual space
+    pub fn selection_visual_col(&self, buffer: &Buffer) -> Column {
+        Column::from(self.selection.point(buffer).col.index + self.selection_virtual_space)
+    }
+
+    /// The columns of virtual space selected, counted from the end of the line
+    pub fn virtual_selection(&self) -> Option<Range<usize>> {
+        if self.virtual_space == self.selection_virtual_space {
+            None
+        } else {
+            Some(self.start_virtual_space()..self.virtual_space.max(self.selection_virtual_space))
+        }
+    }
+
+    /// The virtual space before the start of the selection, which have to be filled with spaces before inserting text
+    pub(super) fn start_virtual_space(&self) -> usize {
+        match (self.virtual_space, self.selection_virtual_space) {
+            (0, _) | (_, 0) => 0,
+            (v, s) => v.min(s),
+        }
+    }
+
+    /// Move the caret one column left in the virtual space. Return false if the caret is not in virtual space.
+    pub(super) fn backward_in_virtual_space(&mut self) -> bool {
+        if self.virtual_space > 0 && self.selection_is_empty() && self.virtual_space == self.selection_virtual_space {
+            self.virtual_space -= 1;
+            self.selection_virtual_space -= 1;
+            self.sticky_col = self.visual_col();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Remove a selection made only of virtual space. Return false if there is no such selection.
+    pub(super) fn collapse_virtual_selection(&mut self) -> bool {
+        if self.selection_is_empty() && self.virtual_space != self.selection_virtual_space {
+            let v = self.virtual_space.min(self.selection_virtual_space);
+            self.virtual_space = v;
+            self.selection_virtual_space = v;
+            self.sticky_col = self.visual_col();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(super) fn clear_virtual_space(&mut self) {
+        self.virtual_space = 0;
+        self.selection_virtual_space = 0;
+    }
+
     pub fn duplicate_to(&self, start: Absolute, end: Absolute, buffer: &Buffer) -> Self {
         let mut c = Caret::new();
         c.set_index(start, true, true, buffer);
@@ -314,7 +526,9 @@ impl Caret {
     pub fn update_after_insert(&mut self, index: Absolute, delta: Relative, buffer: &Buffer) {
         if self.index > index {
             let col = self.col();
+            let virtual_space = self.virtual_space;
             self.set_index(self.index + delta, false, false, buffer);
+            self.virtual_space = virtual_space;
             // Update virtal column position only if the real column position changed
             if col != self.col() {
                 self.sticky_col = col;
@@ -327,7 +541,11 @@ impl Caret {
     pub fn update_after_delete(&mut self, index: Absolute, delta: Relative, buffer: &Buffer) {
         if self.index > index {
             let col = self.col();
-            self.set_index(self.index - delta, false, false, buffer);
+            // a caret inside the deleted range goes to its start
+            let i = if self.index >= index + delta { self.index - delta } else { index };
+            let virtual_space = self.virtual_space;
+            self.set_index(i, false, false, buffer);
+            self.virtual_space = virtual_space;
             // Update virtal column position only if the real column position changed
             if col != self.col() {
                 self.sticky_col = col;
@@ -335,7 +553,11 @@ impl Caret {
         }
 
         if self.selection > index {
-            self.selection -= delta;
+            self.selection = if self.selection >= index + delta {
+                self.selection - delta
+            } else {
+                index
+            };
         }
     }
 }
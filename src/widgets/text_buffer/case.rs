@@ -0,0 +1,152 @@
+use super::buffer::Buffer;
+use super::position::Absolute;
+use super::rope_utils;
+
+/// The case conversions of `Buffer::convert_case`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Upper,
+    Lower,
+    /// The first letter of each word in upper case, the others in lower case
+    Title,
+    Snake,
+    Camel,
+    Pascal,
+    Kebab,
+    Constant,
+    /// Swap the case of each letter
+    Toggle,
+}
+
+impl Case {
+    /// Convert `text`. The identifier cases convert each identifier on its own, keeping the chars between them,
+    /// and the underscores around them.
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Case::Upper => text.to_uppercase(),
+            Case::Lower => text.to_lowercase(),
+            Case::Toggle => text
+                .chars()
+                .map(|c| {
+                    if c.is_uppercase() {
+                        c.to_lowercase().to_string()
+                    } else {
+                        c.to_uppercase().to_string()
+                    }
+                })
+                .collect(),
+            Case::Title => {
+                let mut result = text.to_owned();
+                for w in rope_utils::split_words(text).into_iter().rev() {
+                    result.replace_range(w.clone(), &capitalize(&text[w]));
+                }
+                result
+            }
+            _ => {
+                let mut result = String::with_capacity(text.len());
+                let mut last = 0;
+                for r in rope_utils::identifier_ranges(text) {
+                    let run = &text[r.clone()];
+                    let identifier = run.trim_matches('_');
+                    let start = r.start + run.len() - run.trim_start_matches('_').len();
+                    let words: Vec<&str> = rope_utils::split_words(identifier)
+                        .into_iter()
+                        .map(|w| &identifier[w])
+                        .collect();
+                    result.push_str(&text[last..start]);
+                    result.push_str(&self.join(&words));
+                    last = start + identifier.len();
+                }
+                result.push_str(&text[last..]);
+                result
+            }
+        }
+    }
+
+    fn join(self, words: &[&str]) -> String {
+        let lower = || words.iter().map(|w| w.to_lowercase());
+        match self {
+            Case::Snake => lower().collect::<Vec<_>>().join("_"),
+            Case::Kebab => lower().collect::<Vec<_>>().join("-"),
+            Case::Constant => words.iter().map(|w| w.to_uppercase()).collect::<Vec<_>>().join("_"),
+            Case::Pascal => words.iter().map(|w| capitalize(w)).collect(),
+            Case::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| if i == 0 { w.to_lowercase() } else { capitalize(w) })
+                .collect(),
+            _ => words.join(" "),
+        }
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+        None => String::new(),
+    }
+}
+
+impl Buffer {
+    /// Convert the selection of each caret, or the word at the caret, to `case`.
+    /// The converted texts are selected.
+    pub fn convert_case(&mut self, case: Case) {
+        let mut carets: Vec<usize> = (0..self.carets.len()).collect();
+        carets.sort_by_key(|i| std::cmp::Reverse(self.carets[*i].start()));
+        for i in carets {
+            let r = if self.carets[i].selection_is_empty() {
+                let w = rope_utils::word_range(&self.slice(..), self.carets[i].index);
+                Absolute::from(w.start)..Absolute::from(w.end)
+            } else {
+                self.carets[i].range()
+            };
+            if r.start == r.end {
+                continue;
+            }
+            let text = case.apply(&self.slice(r.clone()).to_string());
+            let backward = !self.carets[i].selection_is_empty() && self.carets[i].index == self.carets[i].start();
+            self.edit(&r, &text);
+            let (anchor, head) = if backward {
+                (r.start + text.len(), r.start)
+            } else {
+                (r.start, r.start + text.len())
+            };
+            self.set_caret_index(i, anchor, true, true);
+            self.set_caret_index(i, head, false, true);
+        }
+        self.carets.merge();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let text = "  parseHTTPResponse_code";
+        assert_eq!(Case::Snake.apply(text), "  parse_http_response_code");
+        assert_eq!(Case::Camel.apply(text), "  parseHttpResponseCode");
+        assert_eq!(Case::Pascal.apply(text), "  ParseHttpResponseCode");
+        assert_eq!(Case::Kebab.apply("fooBar baz_qux\nBaz"), "foo-bar baz-qux\nbaz");
+        assert_eq!(Case::Constant.apply("max-size"), "MAX_SIZE");
+        assert_eq!(Case::Title.apply("hello, big world"), "Hello, Big World");
+        assert_eq!(Case::Toggle.apply("aBc"), "AbC");
+    }
+
+    #[test]
+    fn separators_are_kept() {
+        assert_eq!(Case::Snake.apply("foo.barBaz(x)"), "foo.bar_baz(x)");
+        assert_eq!(Case::Camel.apply("a - b_c, _d_e;\r\n"), "a - bC, _dE;\r\n");
+        assert_eq!(Case::Constant.apply("obj->max-size"), "OBJ->MAX_SIZE");
+    }
+
+    #[test]
+    fn convert_words_at_carets() {
+        let mut b = Buffer::with_caret("fooBar baz", 2..2);
+        b.convert_case(Case::Snake);
+        assert_eq!(b.rope.to_string(), "foo_bar baz");
+        assert_eq!(b.main_caret().range(), Absolute::from(0)..Absolute::from(7));
+    }
+}
@@ -0,0 +1,256 @@
+use std::ops::Range;
+
+use syntect::parsing::SyntaxReference;
+
+use super::buffer::Buffer;
+use super::position::{Absolute, Column, Line};
+use super::syntax::metadata_items;
+
+/// A language name, its line comment and its block comment tokens
+type LanguageTokens = (&'static str, Option<&'static str>, Option<(&'static str, &'static str)>);
+
+/// Comment tokens of the languages the syntect metadata doesn't know
+const FALLBACK_TOKENS: &[LanguageTokens] = &[
+    ("Rust", Some("//"), Some(("/*", "*/"))),
+    ("C", Some("//"), Some(("/*", "*/"))),
+    ("C++", Some("//"), Some(("/*", "*/"))),
+    ("C#", Some("//"), Some(("/*", "*/"))),
+    ("D", Some("//"), Some(("/*", "*/"))),
+    ("Go", Some("//"), Some(("/*", "*/"))),
+    ("Java", Some("//"), Some(("/*", "*/"))),
+    ("JavaScript", Some("//"), Some(("/*", "*/"))),
+    ("TypeScript", Some("//"), Some(("/*", "*/"))),
+    ("Objective-C", Some("//"), Some(("/*", "*/"))),
+    ("PHP", Some("//"), Some(("/*", "*/"))),
+    ("Scala", Some("//"), Some(("/*", "*/"))),
+    ("CSS", None, Some(("/*", "*/"))),
+    ("Python", Some("#"), None),
+    ("Ruby", Some("#"), Some(("=begin", "=end"))),
+    ("Perl", Some("#"), None),
+    ("Bourne Again Shell (bash)", Some("#"), None),
+    ("Makefile", Some("#"), None),
+    ("YAML", Some("#"), None),
+    ("TOML", Some("#"), None),
+    ("R", Some("#"), None),
+    ("Lua", Some("--"), Some(("--[[", "]]"))),
+    ("Haskell", Some("--"), Some(("{-", "-}"))),
+    ("SQL", Some("--"), Some(("/*", "*/"))),
+    ("Lisp", Some(";"), None),
+    ("Clojure", Some(";"), None),
+    ("Erlang", Some("%"), None),
+    ("LaTeX", Some("%"), None),
+    ("TeX", Some("%"), None),
+    ("Batch File", Some("REM"), None),
+    ("OCaml", None, Some(("(*", "*)"))),
+    ("HTML", None, Some(("<!--", "-->"))),
+    ("XML", None, Some(("<!--", "-->"))),
+    ("Markdown", None, Some(("<!--", "-->"))),
+];
+
+/// The comment tokens of a language
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CommentTokens {
+    pub line: Option<&'static str>,
+    pub block: Option<(&'static str, &'static str)>,
+}
+
+impl CommentTokens {
+    /// The tokens given by the syntax metadata, completed by the fallback table
+    pub fn for_syntax(syntax: &SyntaxReference) -> Self {
+        let items = metadata_items(syntax);
+        let fallback = FALLBACK_TOKENS.iter().find(|t| t.0 == syntax.name);
+        CommentTokens {
+            line: items
+                .iter()
+                .find_map(|i| i.line_comment.as_deref())
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .or_else(|| fallback.and_then(|t| t.1)),
+            block: items
+                .iter()
+                .find_map(|i| i.block_comment.as_ref())
+                .map(|(start, end)| (start.trim(), end.trim()))
+                .filter(|(start, end)| !start.is_empty() && !end.is_empty())
+                .or_else(|| fallback.and_then(|t| t.2)),
+        }
+    }
+}
+
+impl Buffer {
+    /// Comment the lines of each caret with `token`, or uncomment them if they are all commented.
+    /// The token is inserted at the smallest indentation of the lines, blank lines are left alone.
+    pub fn toggle_line_comment(&mut self, token: &str) {
+        for b in self.caret_line_blocks().into_iter().rev() {
+            let lines: Vec<Line> = b.map(Line::from).filter(|l| !self.is_blank_line(*l)).collect();
+            if lines.is_empty() {
+                continue;
+            }
+            let commented = lines.iter().all(|l| {
+                self.slice(l.absolute_indentation(self)..l.end(self))
+                    .to_string()
+                    .starts_with(token)
+            });
+            if commented {
+                for l in lines.iter().rev() {
+                    let start = l.absolute_indentation(self);
+                    let mut end = start + token.len();
+                    if end < l.end(self) && self.rope.byte(end.index) == b' ' {
+                        end += 1;
+                    }
+                    self.edit_after_carets(start..end, "");
+                }
+            } else {
+                let indentation = lines.iter().map(|l| l.indentation(self)).min().unwrap_or_default();
+                for l in lines.iter().rev() {
+                    let at = self.column_in_indentation(*l, indentation);
+                    self.edit_after_carets(at..at, &format!("{} ", token));
+                }
+            }
+        }
+    }
+
+    /// Surround each selection, or the line of an empty caret, with `start` and `end`,
+    /// or remove them if it is already surrounded
+    pub fn toggle_block_comment(&mut self, start: &str, end: &str) {
+        let mut ranges: Vec<Range<Absolute>> = self
+            .carets
+            .iter()
+            .map(|c| {
+                if c.selection_is_empty() {
+                    let l = c.line();
+                    l.absolute_indentation(self)..l.end(self)
+                } else {
+                    c.range()
+                }
+            })
+            .collect();
+        ranges.sort_by_key(|r| std::cmp::Reverse(r.start));
+        ranges.dedup();
+
+        for r in ranges {
+            match self.block_comment_around(&r, start, end) {
+                Some((open, close)) => {
+                    self.edit(&close, "");
+                    self.edit(&open, "");
+                }
+                None => {
+                    self.edit(&(r.end..r.end), &format!(" {}", end));
+                    self.edit(&(r.start..r.start), &format!("{} ", start));
+                }
+            }
+        }
+    }
+
+    /// The ranges of the comment tokens, with their inner space, inside or just around `r`
+    fn block_comment_around(
+        &self,
+        r: &Range<Absolute>,
+        start: &str,
+        end: &str,
+    ) -> Option<(Range<Absolute>, Range<Absolute>)> {
+        let text = self.slice(r.clone()).to_string();
+        let trimmed = text.trim();
+        if trimmed.len() >= start.len() + end.len() && trimmed.starts_with(start) && trimmed.ends_with(end) {
+            let open_start = r.start + (text.len() - text.trim_start().len());
+            let close_end = r.start + text.trim_end().len();
+            let mut open = open_start..open_start + start.len();
+            let mut close = close_end - end.len()..close_end;
+            if open.end < close.start && self.rope.byte(open.end.index) == b' ' {
+                open.end += 1;
+            }
+            if open.end < close.start && self.rope.byte(close.start.index - 1) == b' ' {
+                close.start = close.start - 1;
+            }
+            return Some((open, close));
+        }
+
+        let open = self.token_before(r.start, start)?;
+        let close = self.token_after(r.end, end)?;
+        Some((open, close))
+    }
+
+    /// The range of `token`, followed or not by a space, ending at `at`
+    fn token_before(&self, at: Absolute, token: &str) -> Option<Range<Absolute>> {
+        [format!("{} ", token), token.to_owned()].iter().find_map(|t| {
+            let start = at.index.checked_sub(t.len())?;
+            self.bytes_at(start, t).then_some(Absolute::from(start)..at)
+        })
+    }
+
+    /// The range of `token`, preceded or not by a space, starting at `at`
+    fn token_after(&self, at: Absolute, token: &str) -> Option<Range<Absolute>> {
+        [format!(" {}", token), token.to_owned()].iter().find_map(|t| {
+            let end = at + t.len();
+            self.bytes_at(at.index, t).then_some(at..end)
+        })
+    }
+
+    /// Check that the bytes at `index` are the ones of `s`, without caring for char boundaries
+    fn bytes_at(&self, index: usize, s: &str) -> bool {
+        index + s.len() <= self.rope.len_bytes() && s.bytes().enumerate().all(|(i, b)| self.rope.byte(index + i) == b)
+    }
+
+    fn is_blank_line(&self, line: Line) -> bool {
+        line.absolute_indentation(self) >= line.end(self)
+    }
+
+    /// The position in the indentation of `line` at the visual column `col`
+    fn column_in_indentation(&self, line: Line, col: Column) -> Absolute {
+        let start = line.start(self);
+        let mut c = 0;
+        let mut offset = 0;
+        for b in self.slice(start..line.absolute_indentation(self)).bytes() {
+            if c >= col.index {
+                break;
+            }
+            c += if b == b'\t' { self.tabsize - c % self.tabsize } else { 1 };
+            offset += 1;
+        }
+        start + offset
+    }
+
+    /// Replace `range` by `text`, carets at the start of `range` being moved after `text`
+    fn edit_after_carets(&mut self, range: Range<Absolute>, text: &str) {
+        let moved: Vec<usize> = self
+            .carets
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.selection_is_empty() && c.index == range.start)
+            .map(|(i, _)| i)
+            .collect();
+        self.edit(&range, text);
+        for i in moved {
+            let index = self.carets[i].index + text.len();
+            self.set_caret_index(i, index, true, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::widgets::text_buffer::syntax::SYNTAXSET;
+
+    #[test]
+    fn toggle_comments() {
+        let mut b = Buffer::with_caret("    a\n  b\n\n", 0..11);
+        b.toggle_line_comment("//");
+        assert_eq!(b.rope.to_string(), "  //   a\n  // b\n\n");
+        b.toggle_line_comment("//");
+        assert_eq!(b.rope.to_string(), "    a\n  b\n\n");
+
+        let mut b = Buffer::from_rope("  let a = 1;".into(), 4);
+        b.toggle_block_comment("/*", "*/");
+        assert_eq!(b.rope.to_string(), "  /* let a = 1; */");
+        b.toggle_block_comment("/*", "*/");
+        assert_eq!(b.rope.to_string(), "  let a = 1;");
+    }
+
+    #[test]
+    fn tokens_from_syntax() {
+        let tokens = |name| CommentTokens::for_syntax(SYNTAXSET.find_syntax_by_name(name).unwrap());
+        assert_eq!(tokens("Rust").line, Some("//"));
+        assert_eq!(tokens("Python").line, Some("#"));
+        assert_eq!(tokens("HTML").block, Some(("<!--", "-->")));
+    }
+}
@@ -1,21 +1,33 @@
-use std::collections::HashMap;
 use std::io::Result;
 use std::ops::{Deref, DerefMut, Range, RangeFrom, RangeTo};
 use std::path::{Path, PathBuf};
 
 use super::buffer::Buffer;
-use super::file::TextFileInfo;
+use super::case::Case;
+use super::comment::CommentTokens;
+use super::file::{LineFeed, TextFileInfo};
+use super::indent::IndentRules;
+use super::line_ops::LineOrder;
+use super::numbers::Sequence;
+use super::pairs::language_pairs;
+use super::position::Absolute;
+use super::search::Matcher;
+use super::surround::Surrounding;
+use super::syntax::StyledLine;
+use super::transform::Transform;
+use super::undo::{EditKind, HistoryState, Transaction, UndoHistory};
 use druid::Data;
-use once_cell::sync::Lazy;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct EditStack {
     pub buffer: Buffer,
-    undo_stack: Vec<Buffer>,
-    redo_stack: Vec<Buffer>,
+    history: UndoHistory,
     pub file: TextFileInfo,
     pub filename: Option<PathBuf>,
-    dirty: bool,
+    /// The revision of the undo history matching the file on disk, if any
+    saved_revision: Option<usize>,
+    /// Whether the undo history is saved with the file, to be restored the next time it is opened
+    keep_history: bool,
 }
 
 impl Data for EditStack {
@@ -23,19 +35,22 @@ impl Data for EditStack {
         self.buffer.same(&other.buffer)
             && self.file == other.file
             && self.filename == other.filename
-            && self.dirty == other.dirty
+            && self.is_dirty() == other.is_dirty()
     }
 }
 
-static AUTO_INSERT_CHARMAP: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
-    let mut m = HashMap::new();
-    m.insert("{", "{}");
-    m.insert("(", "()");
-    m.insert("<", "<>");
-    m.insert("[", "[]");
-    m.insert("\"", "\"\"");
-    m
-});
+impl Default for EditStack {
+    fn default() -> Self {
+        Self {
+            buffer: Buffer::default(),
+            history: UndoHistory::default(),
+            file: TextFileInfo::default(),
+            filename: None,
+            saved_revision: Some(0),
+            keep_history: false,
+        }
+    }
+}
 
 impl EditStack {
     pub fn new() -> Self {
@@ -46,6 +61,15 @@ impl EditStack {
         self.buffer.selected_text(self.file.linefeed)
     }
 
+    /// The text of a block selection, one line per caret, if the carets form a block
+    pub fn selected_block_text(&self) -> Option<String> {
+        if self.buffer.is_block_selection() {
+            Some(self.buffer.block_text(self.file.linefeed))
+        } else {
+            None
+        }
+    }
+
     pub fn main_cursor_selected_text(&self) -> String {
         self.buffer.main_cursor_selected_text()
     }
@@ -53,21 +77,37 @@ impl EditStack {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let file = TextFileInfo::load(&path)?;
         let buffer = Buffer::from_rope(file.1, file.0.indentation.visible_len());
+        // the history is kept for the files it was kept for before
+        let history = UndoHistory::load(path.as_ref(), &buffer.rope);
+        let keep_history = history.is_some();
+        let history = history.unwrap_or_default();
         Ok(Self {
             buffer,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            saved_revision: Some(history.revision()),
+            history,
             file: file.0,
             filename: Some(path.as_ref().to_path_buf()),
-            dirty: false,
+            keep_history,
         })
     }
 
+    /// A new untitled buffer containing `text`, with the file settings of `file`
+    pub fn untitled(text: &str, file: TextFileInfo) -> Self {
+        Self {
+            buffer: Buffer::from_rope(text.into(), file.indentation.visible_len()),
+            file,
+            saved_revision: None,
+            ..Default::default()
+        }
+    }
+
     pub fn open<P>(&mut self, path: P) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        let editor = EditStack::from_file(path)?;
+        let mut editor = EditStack::from_file(path)?;
+        // the undo memory limit is a setting of the window, not of the file
+        editor.set_undo_memory_limit(self.history.memory_limit());
         let _ = std::mem::replace(self, editor);
         Ok(())
     }
@@ -81,82 +121,171 @@ impl EditStack {
         }
     }
 
+    /// Check if `path` is the opened file, even through a relative path or a symlink
+    pub fn is_file(&self, path: &Path) -> bool {
+        self.filename
+            .as_ref()
+            .is_some_and(|f| match (f.canonicalize(), path.canonicalize()) {
+                (Ok(f), Ok(path)) => f == path,
+                _ => f == path,
+            })
+    }
+
     pub fn is_dirty(&self) -> bool {
-        self.dirty
+        self.saved_revision != Some(self.history.revision())
     }
 
     pub fn reset_dirty(&mut self) {
-        self.dirty = false;
+        self.saved_revision = Some(self.history.revision());
     }
 
     pub fn set_dirty(&mut self) {
-        self.dirty = true;
+        self.saved_revision = None;
     }
 
     pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         self.file.save_as(&self.buffer, &path)?;
         self.filename = Some(path.as_ref().to_path_buf());
-        self.dirty = false;
-        self.undo_stack.clear();
-        self.redo_stack.clear();
+        self.reset_dirty();
+        self.history.break_group();
+        if self.keep_history {
+            self.save_history();
+        }
         Ok(())
     }
 
-    pub fn undo(&mut self) {
-        if let Some(buffer) = self.undo_stack.pop() {
-            let b = std::mem::take(&mut self.buffer);
-            self.redo_stack.push(b);
-            self.buffer = buffer;
+    /// Save the undo history of the file in the background. The clones of the history share their edits.
+    fn save_history(&self) {
+        if let Some(path) = self.filename.clone() {
+            let (history, rope) = (self.history.clone(), self.buffer.rope.clone());
+            std::thread::spawn(move || {
+                if let Err(e) = history.save(&path, &rope) {
+                    tracing::warn!("Can't save the undo history of {}: {}", path.display(), e);
+                }
+            });
         }
-        if self.undo_stack.is_empty() {
-            self.dirty = false;
+    }
+
+    /// Start or stop keeping the undo history of the file after it is closed. Returns whether it is kept.
+    pub fn toggle_keep_history(&mut self) -> Result<bool> {
+        let path = self
+            .filename
+            .clone()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "The file must be saved first"))?;
+        self.keep_history = !self.keep_history;
+        if self.keep_history {
+            // the history matches the file on disk only when there is no unsaved change
+            if !self.is_dirty() {
+                self.save_history();
+            }
+        } else {
+            UndoHistory::forget(&path)?;
         }
+        Ok(self.keep_history)
+    }
+
+    pub fn undo(&mut self) {
+        self.history.undo(&mut self.buffer);
     }
 
     pub fn redo(&mut self) {
-        if let Some(buffer) = self.redo_stack.pop() {
-            let b = std::mem::take(&mut self.buffer);
-            self.undo_stack.push(b);
-            self.buffer = buffer;
-        }
+        self.history.redo(&mut self.buffer);
+    }
+
+    /// The states of the undo tree, the most recent first
+    pub fn undo_states(&self) -> Vec<HistoryState> {
+        self.history.states()
+    }
+
+    /// Go back or forward to a state listed by `undo_states`
+    pub fn goto_undo_state(&mut self, id: usize) {
+        self.history.goto(id, &mut self.buffer);
+    }
+
+    /// Set the memory the undo history can use, dropping the oldest edits if needed
+    pub fn set_undo_memory_limit(&mut self, limit: usize) {
+        self.history.set_memory_limit(limit);
     }
 
-    fn push_edit(&mut self, buffer: Buffer) {
-        let b = std::mem::take(&mut self.buffer);
-        self.undo_stack.push(b);
+    fn push_edit(&mut self, mut buffer: Buffer, kind: EditKind) {
+        let ops = buffer.take_journal();
+        if !ops.is_empty() {
+            self.history
+                .push(Transaction::new(kind, ops, &self.buffer.carets, &buffer.carets));
+        }
         self.buffer = buffer;
-        self.redo_stack.clear();
-        self.dirty = true;
     }
 
     pub fn insert(&mut self, text: &str) {
         let mut buf = self.buffer.clone();
 
-        match text {
+        let kind = match text {
             linefeed if linefeed == self.file.linefeed.to_str() => {
-                buf.insert(text, false);
-                buf.indent(self.file.indentation);
-            }
-            s if AUTO_INSERT_CHARMAP.get(s).is_some() => {
-                let inner_text = buf.selected_text(self.file.linefeed);
-                buf.insert(AUTO_INSERT_CHARMAP[text], false);
-                buf.backward(false, false);
-                buf.insert(&inner_text, true);
+                buf.new_line(
+                    self.file.indentation,
+                    IndentRules::for_syntax(self.file.syntax),
+                    self.file.linefeed,
+                );
+                EditKind::Other
             }
             _ => {
                 buf.insert(text, false);
+                EditKind::Insert
             }
+        };
+
+        self.push_edit(buf, kind);
+    }
+
+    /// Insert text typed on the keyboard, auto closing the pairs and the tags of the file language.
+    /// `styled` is the highlighting of the lines, telling where the strings, comments and tags are.
+    pub fn type_text(&mut self, text: &str, styled: &[StyledLine]) {
+        let mut chars = text.chars();
+        let c = match (chars.next(), chars.next()) {
+            (Some(c), None) => c,
+            _ => return self.insert(text),
+        };
+        let mut buf = self.buffer.clone();
+        let rope = &self.buffer.rope;
+        buf.type_char(c, language_pairs(&self.file.syntax.name), |a| {
+            let line = rope.byte_to_line(a.index);
+            styled
+                .get(line)
+                .is_some_and(|l| l.in_literal(a.index - rope.line_to_byte(line)))
+        });
+        match c {
+            '>' => buf.close_tags(styled, self.is_html()),
+            '/' => buf.complete_closing_tags(styled, self.is_html()),
+            _ => (),
         }
+        buf.dedent_typed_lines(self.file.indentation, IndentRules::for_syntax(self.file.syntax));
+        self.push_edit(buf, EditKind::Insert);
+    }
+
+    /// Put carets on the name of the tag at the main caret and on the one of its partner, to rename both
+    pub fn select_tag_pair(&mut self, styled: &[StyledLine]) -> bool {
+        let html = self.is_html();
+        self.buffer.select_tag_pair(styled, html)
+    }
+
+    fn is_html(&self) -> bool {
+        self.file.syntax.name.contains("HTML")
+    }
 
-        self.push_edit(buf);
+    /// Paste a block copied from a block selection, column-wise
+    pub fn insert_block(&mut self, text: &str) {
+        let mut buf = self.buffer.clone();
+        let lines: Vec<&str> = text.lines().collect();
+        buf.insert_block(&lines, self.file.linefeed);
+        self.push_edit(buf, EditKind::Other);
     }
 
     pub fn backspace(&mut self) {
         let mut buf = self.buffer.clone();
 
         // TODO check if old buf is same that new with the Data trait
-        if buf.backspace() {
-            self.push_edit(buf);
+        if buf.backspace(language_pairs(&self.file.syntax.name), self.file.indentation) {
+            self.push_edit(buf, EditKind::Backspace);
         }
     }
 
@@ -164,14 +293,214 @@ impl EditStack {
         let mut buf = self.buffer.clone();
 
         if buf.delete() {
-            self.push_edit(buf);
+            self.push_edit(buf, EditKind::Delete);
+        }
+    }
+
+    pub fn replace_next(&mut self, matcher: &Matcher, replacement: &str, wrap_around: bool) {
+        let mut buf = self.buffer.clone();
+        if buf.replace_next(matcher, replacement, wrap_around) {
+            self.push_edit(buf, EditKind::Other);
+        } else {
+            self.buffer = buf;
         }
     }
 
+    pub fn replace_all(&mut self, matcher: &Matcher, replacement: &str) -> usize {
+        let mut buf = self.buffer.clone();
+        let count = buf.replace_all(matcher, replacement, 0.into()..buf.len());
+        if count > 0 {
+            self.push_edit(buf, EditKind::Other);
+        }
+        count
+    }
+
+    pub fn replace_matches(&mut self, matcher: &Matcher, replacement: &str, ranges: &[Range<Absolute>]) -> usize {
+        let mut buf = self.buffer.clone();
+        let count = buf.replace_matches(matcher, replacement, ranges);
+        if count > 0 {
+            self.push_edit(buf, EditKind::Other);
+        }
+        count
+    }
+
+    pub fn replace_in_selection(&mut self, matcher: &Matcher, replacement: &str) -> usize {
+        let mut buf = self.buffer.clone();
+        let count = buf.replace_in_selection(matcher, replacement);
+        if count > 0 {
+            self.push_edit(buf, EditKind::Other);
+        }
+        count
+    }
+
     pub fn tab(&mut self) {
         let mut buf = self.buffer.clone();
         buf.tab(self.file.indentation);
-        self.push_edit(buf);
+        self.push_edit(buf, EditKind::Other);
+    }
+
+    pub fn outdent(&mut self) {
+        let mut buf = self.buffer.clone();
+        buf.outdent_lines(self.file.indentation);
+        self.push_edit(buf, EditKind::Other);
+    }
+
+    pub fn convert_case(&mut self, case: Case) {
+        let mut buf = self.buffer.clone();
+        buf.convert_case(case);
+        self.push_edit(buf, EditKind::Other);
+    }
+
+    /// Transform the selections, or leave the buffer unchanged and return the error of the first one failing
+    pub fn transform_selections(&mut self, transform: Transform) -> anyhow::Result<()> {
+        let mut buf = self.buffer.clone();
+        buf.transform_selections(transform, self.file.linefeed)?;
+        self.push_edit(buf, EditKind::Other);
+        Ok(())
+    }
+
+    pub fn insert_sequence(&mut self, sequence: &Sequence) {
+        let mut buf = self.buffer.clone();
+        buf.insert_sequence(sequence);
+        self.push_edit(buf, EditKind::Other);
+    }
+
+    pub fn increment_numbers(&mut self, delta: i64) {
+        let mut buf = self.buffer.clone();
+        if buf.increment_numbers(delta) {
+            self.push_edit(buf, EditKind::Other);
+        }
+    }
+
+    pub fn align_carets(&mut self) {
+        let mut buf = self.buffer.clone();
+        buf.align_carets();
+        self.push_edit(buf, EditKind::Other);
+    }
+
+    pub fn align_on(&mut self, delimiter: &str) -> usize {
+        let mut buf = self.buffer.clone();
+        let count = buf.align_on(delimiter);
+        if count > 0 {
+            self.push_edit(buf, EditKind::Other);
+        }
+        count
+    }
+
+    fn edit_lines(&mut self, f: impl FnOnce(&mut Buffer, LineFeed)) {
+        let mut buf = self.buffer.clone();
+        f(&mut buf, self.file.linefeed);
+        self.push_edit(buf, EditKind::Other);
+    }
+
+    pub fn move_lines_up(&mut self) {
+        self.edit_lines(Buffer::move_lines_up);
+    }
+
+    pub fn move_lines_down(&mut self) {
+        self.edit_lines(Buffer::move_lines_down);
+    }
+
+    pub fn duplicate_lines(&mut self) {
+        self.edit_lines(Buffer::duplicate_lines);
+    }
+
+    pub fn delete_lines(&mut self) {
+        self.edit_lines(|buf, _| buf.delete_lines());
+    }
+
+    pub fn join_lines(&mut self) {
+        self.edit_lines(Buffer::join_lines);
+    }
+
+    pub fn insert_line_below(&mut self) {
+        self.edit_lines(Buffer::insert_line_below);
+    }
+
+    pub fn insert_line_above(&mut self) {
+        self.edit_lines(Buffer::insert_line_above);
+    }
+
+    pub fn sort_lines(&mut self, order: LineOrder) {
+        self.edit_lines(|buf, line_feed| buf.sort_lines(order, line_feed));
+    }
+
+    /// Toggle the line comments of the lines of each caret, or a block comment if the language has no line comment
+    pub fn toggle_line_comment(&mut self) {
+        let tokens = CommentTokens::for_syntax(self.file.syntax);
+        let mut buf = self.buffer.clone();
+        match tokens {
+            CommentTokens { line: Some(token), .. } => buf.toggle_line_comment(token),
+            CommentTokens {
+                block: Some((start, end)),
+                ..
+            } => buf.toggle_block_comment(start, end),
+            _ => return,
+        }
+        self.push_edit(buf, EditKind::Other);
+    }
+
+    /// Toggle a block comment around each selection, or line comments if the language has no block comment
+    pub fn toggle_block_comment(&mut self) {
+        let tokens = CommentTokens::for_syntax(self.file.syntax);
+        let mut buf = self.buffer.clone();
+        match tokens {
+            CommentTokens {
+                block: Some((start, end)),
+                ..
+            } => buf.toggle_block_comment(start, end),
+            CommentTokens { line: Some(token), .. } => buf.toggle_line_comment(token),
+            _ => return,
+        }
+        self.push_edit(buf, EditKind::Other);
+    }
+
+    /// Surround the selections with a bracket, a quote or a tag parsed by `Surrounding::parse`.
+    /// Returns false if `surrounding` can't be parsed.
+    pub fn surround_with(&mut self, surrounding: &str) -> bool {
+        let surrounding = match Surrounding::parse(surrounding) {
+            Some(s) => s,
+            None => return false,
+        };
+        let mut buf = self.buffer.clone();
+        buf.surround(&surrounding);
+        self.push_edit(buf, EditKind::Other);
+        true
+    }
+
+    /// Replace the brackets, quotes or tag around each caret by the ones parsed from `surrounding`.
+    /// `styled` is the highlighting of the lines, telling where the code brackets, the strings and the tags are.
+    /// Returns false if `surrounding` can't be parsed.
+    pub fn change_surrounding(&mut self, surrounding: &str, styled: &[StyledLine]) -> bool {
+        let surrounding = match Surrounding::parse(surrounding) {
+            Some(s) => s,
+            None => return false,
+        };
+        let mut buf = self.buffer.clone();
+        if buf.replace_surrounding(Some(&surrounding), styled, self.is_html()) {
+            self.push_edit(buf, EditKind::Other);
+        }
+        true
+    }
+
+    /// Remove the brackets, quotes or tag around each caret
+    pub fn delete_surrounding(&mut self, styled: &[StyledLine]) {
+        let mut buf = self.buffer.clone();
+        if buf.replace_surrounding(None, styled, self.is_html()) {
+            self.push_edit(buf, EditKind::Other);
+        }
+    }
+
+    /// Keep the lines matching `matcher`, or remove them when `keep` is false. Returns the number of removed lines.
+    pub fn filter_lines(&mut self, matcher: &Matcher, keep: bool) -> usize {
+        let mut removed = 0;
+        self.edit_lines(|buf, line_feed| removed = buf.filter_lines(matcher, keep, line_feed));
+        removed
+    }
+
+    /// The lines matching `matcher`, joined with the line feed of the file
+    pub fn lines_matching(&self, matcher: &Matcher) -> String {
+        self.buffer.lines_matching(matcher).join(self.file.linefeed.to_str())
     }
 }
 
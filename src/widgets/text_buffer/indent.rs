@@ -0,0 +1,163 @@
+use once_cell::sync::Lazy;
+use syntect::parsing::{Regex, SyntaxReference};
+
+use super::buffer::Buffer;
+use super::file::{Indentation, LineFeed};
+use super::position::{Absolute, Column, Line};
+use super::syntax::metadata_items;
+
+static BRACKETS_INCREASE: Lazy<Regex> = Lazy::new(|| Regex::new(r"[\{\(\[]\s*$".to_owned()));
+static PYTHON_INCREASE: Lazy<Regex> = Lazy::new(|| Regex::new(r"[:\{\(\[]\s*$".to_owned()));
+// an opening tag, not self-closing, at the end of the line
+static MARKUP_INCREASE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"([\{\(\[]|<[A-Za-z][^<>]*[^/<>]>|<[A-Za-z]>)\s*$".to_owned()));
+static DEFAULT_DECREASE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*([\}\)\]]|</)".to_owned()));
+
+/// When the indentation of a line goes up or down, from the TextMate indentation settings of the language,
+/// or else from the brackets, the colons in Python and the tags in HTML and XML
+#[derive(Debug, Clone, Copy)]
+pub struct IndentRules {
+    /// A line matching it indents the next one
+    increase: &'static Regex,
+    /// A line matching it is dedented from the previous one
+    decrease: &'static Regex,
+}
+
+impl IndentRules {
+    pub fn for_syntax(syntax: &SyntaxReference) -> Self {
+        let items = metadata_items(syntax);
+        IndentRules {
+            increase: items
+                .iter()
+                .find_map(|i| i.increase_indent_pattern.as_ref())
+                .unwrap_or_else(|| match syntax.name.as_str() {
+                    "Python" => &PYTHON_INCREASE,
+                    "HTML" | "XML" => &MARKUP_INCREASE,
+                    _ => &BRACKETS_INCREASE,
+                }),
+            decrease: items
+                .iter()
+                .find_map(|i| i.decrease_indent_pattern.as_ref())
+                .unwrap_or(&DEFAULT_DECREASE),
+        }
+    }
+
+    pub fn increase(&self, line: &str) -> bool {
+        self.increase.is_match(line)
+    }
+
+    pub fn decrease(&self, line: &str) -> bool {
+        self.decrease.is_match(line)
+    }
+}
+
+/// The text of an indentation `col` wide
+fn indentation_text(col: Column, indentation: Indentation) -> String {
+    match indentation {
+        Indentation::Space(_) => " ".repeat(col.index),
+        Indentation::Tab(_) => {
+            let n = indentation.visible_len();
+            format!("{}{}", "\t".repeat(col.index / n), " ".repeat(col.index % n))
+        }
+    }
+}
+
+impl Buffer {
+    /// Break the line at each caret, indenting the new line like the current one, one level more
+    /// after a line increasing the indentation. Between an opening and a closing token, like an empty `{}`,
+    /// the closing one goes on its own line.
+    pub fn new_line(&mut self, indentation: Indentation, rules: IndentRules, line_feed: LineFeed) {
+        for i in 0..self.carets.len() {
+            self.fill_virtual_space(i);
+            let r = self.carets[i].range();
+            let line = self.carets[i].start_line(self);
+            let before = self.slice(line.start(self)..r.start).to_string();
+            let after = self.slice(r.end..line.end(self)).to_string();
+            let base = &before[..before.len() - before.trim_start().len()];
+
+            let (text, caret) = if rules.increase(&before) {
+                let inner = format!(
+                    "{}{}",
+                    base,
+                    indentation_text(Column::from(indentation.visible_len()), indentation)
+                );
+                let caret = line_feed.to_str().len() + inner.len();
+                if rules.decrease(&after) {
+                    (format!("{lf}{}{lf}{}", inner, base, lf = line_feed.to_str()), caret)
+                } else {
+                    (format!("{}{}", line_feed.to_str(), inner), caret)
+                }
+            } else {
+                let text = format!("{}{}", line_feed.to_str(), base);
+                let caret = text.len();
+                (text, caret)
+            };
+            self.edit(&r, &text);
+            self.set_caret_index(i, r.start + caret, true, true);
+        }
+        self.carets.merge();
+    }
+
+    /// Dedent the lines of the carets that decrease the indentation, like a line with only a `}` typed,
+    /// if they are more indented than the previous line allows
+    pub fn dedent_typed_lines(&mut self, indentation: Indentation, rules: IndentRules) {
+        let mut lines: Vec<Line> = self
+            .carets
+            .iter()
+            .filter(|c| c.selection_is_empty())
+            .map(|c| c.line())
+            .collect();
+        lines.sort_by_key(|l| std::cmp::Reverse(l.index));
+        lines.dedup();
+        for line in lines {
+            let content = self.line_content(line.index);
+            if content.trim().is_empty() || !rules.decrease(&content) {
+                continue;
+            }
+            let prev = match (0..line.index).rev().find(|l| !self.line_content(*l).trim().is_empty()) {
+                Some(prev) => Line::from(prev),
+                None => continue,
+            };
+            let mut target = prev.indentation(self).index;
+            if !rules.increase(&self.line_content(prev.index)) {
+                target = target.saturating_sub(indentation.visible_len());
+            }
+            if line.indentation(self).index > target {
+                let range: std::ops::Range<Absolute> = line.start(self)..line.absolute_indentation(self);
+                self.edit(&range, &indentation_text(Column::from(target), indentation));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::widgets::text_buffer::syntax::SYNTAXSET;
+
+    fn rules(name: &str) -> IndentRules {
+        IndentRules::for_syntax(SYNTAXSET.find_syntax_by_name(name).unwrap())
+    }
+
+    #[test]
+    fn indent_rules() {
+        assert!(rules("Rust").increase("fn main() {"));
+        assert!(rules("Rust").decrease("    }"));
+        assert!(rules("Python").increase("def f():"));
+        assert!(rules("HTML").increase("  <div class=\"a\">"));
+        assert!(!rules("HTML").increase("  <br/>"));
+    }
+
+    #[test]
+    fn new_line_and_dedent() {
+        let mut b = Buffer::with_caret("  f() {}", 7..7);
+        b.new_line(Indentation::Space(4), rules("Rust"), LineFeed::Lf);
+        assert_eq!(b.rope.to_string(), "  f() {\n      \n  }");
+        assert_eq!(b.main_caret().index, Absolute::from(14));
+
+        b.new_line(Indentation::Space(4), rules("Rust"), LineFeed::Lf);
+        b.insert("}", false);
+        b.dedent_typed_lines(Indentation::Space(4), rules("Rust"));
+        assert_eq!(b.rope.to_string(), "  f() {\n      \n  }\n  }");
+    }
+}
@@ -0,0 +1,546 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hasher};
+use std::iter::Peekable;
+use std::ops::{Range, RangeInclusive};
+use std::str::Chars;
+
+use super::buffer::Buffer;
+use super::caret::Carets;
+use super::file::LineFeed;
+use super::position::{Absolute, Line};
+use super::search::Matcher;
+
+/// A caret end as a line index and a byte offset in this line, so it can follow its line when lines are moved
+type LinePosition = (usize, usize);
+
+/// How `Buffer::sort_lines` reorders the lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineOrder {
+    Lexicographic,
+    CaseInsensitive,
+    /// Numbers in the lines are compared by value, so `a2` comes before `a10`
+    Natural,
+    Length,
+    Reverse,
+    /// Remove the duplicated lines, keeping the first one
+    Unique,
+    Shuffle,
+}
+
+impl LineOrder {
+    fn apply(self, lines: &mut Vec<String>) {
+        match self {
+            LineOrder::Lexicographic => lines.sort(),
+            LineOrder::CaseInsensitive => lines.sort_by_cached_key(|l| l.to_lowercase()),
+            LineOrder::Natural => lines.sort_by(|a, b| natural_cmp(a, b)),
+            LineOrder::Length => lines.sort_by_key(|l| l.chars().count()),
+            LineOrder::Reverse => lines.reverse(),
+            LineOrder::Unique => {
+                let mut seen = HashSet::new();
+                lines.retain(|l| seen.insert(l.clone()));
+            }
+            LineOrder::Shuffle => shuffle(lines),
+        }
+    }
+}
+
+impl Buffer {
+    /// The lines touched by the carets, merged when they overlap or touch, sorted.
+    /// A selection ending at the start of a line doesn't include this line.
+    pub(super) fn caret_line_blocks(&self) -> Vec<RangeInclusive<usize>> {
+        let mut blocks: Vec<RangeInclusive<usize>> = self
+            .carets
+            .iter()
+            .map(|c| {
+                let first = c.start_line(self).index;
+                let mut last = c.end_line(self).index;
+                if last > first && c.end() == Line::from(last).start(self) {
+                    last -= 1;
+                }
+                first..=last
+            })
+            .collect();
+        blocks.sort_by_key(|b| *b.start());
+        merge_blocks(blocks, 1)
+    }
+
+    /// The text of a line, without its line feed
+    pub(super) fn line_content(&self, line: usize) -> String {
+        let line = Line::from(line);
+        self.slice(line.start(self)..line.end(self)).to_string()
+    }
+
+    /// Replace the text of `lines`, without the line feed of the last one, by `new_lines`
+    pub(super) fn replace_lines(&mut self, lines: RangeInclusive<usize>, new_lines: &[String], line_feed: LineFeed) {
+        let range = Line::from(*lines.start()).start(self)..Line::from(*lines.end()).end(self);
+        self.edit(&range, &new_lines.join(line_feed.to_str()));
+    }
+
+    fn line_position(&self, a: Absolute) -> LinePosition {
+        let line = self.rope.byte_to_line(a.index);
+        (line, a.index - self.rope.line_to_byte(line))
+    }
+
+    fn absolute_from_line_position(&self, (line, offset): LinePosition) -> Absolute {
+        let line = Line::from(line.min(self.len_lines() - 1));
+        (line.start(self) + offset).min(line.end(self))
+    }
+
+    fn caret_line_positions(&self) -> Vec<(LinePosition, LinePosition, bool)> {
+        self.carets
+            .positions()
+            .into_iter()
+            .map(|(index, selection, is_clone)| (self.line_position(index), self.line_position(selection), is_clone))
+            .collect()
+    }
+
+    fn set_caret_line_positions(&mut self, positions: &[(LinePosition, LinePosition, bool)]) {
+        let positions: Vec<_> = positions
+            .iter()
+            .map(|&(index, selection, is_clone)| {
+                (
+                    self.absolute_from_line_position(index),
+                    self.absolute_from_line_position(selection),
+                    is_clone,
+                )
+            })
+            .collect();
+        self.carets = Carets::from_positions(&positions, self);
+    }
+
+    /// The range of `lines` with one line feed, the one before them when they end the buffer
+    fn line_block_range(&self, lines: RangeInclusive<usize>) -> Range<Absolute> {
+        let (first, last) = (Line::from(*lines.start()), Line::from(*lines.end()));
+        match (last.next(self), first.prev()) {
+            (Some(next), _) => first.start(self)..next.start(self),
+            (None, Some(prev)) => prev.end(self)..self.len(),
+            (None, None) => Absolute::from(0)..self.len(),
+        }
+    }
+
+    /// Move the lines of each caret above the previous line
+    pub fn move_lines_up(&mut self, line_feed: LineFeed) {
+        let blocks = self.caret_line_blocks();
+        if blocks.first().is_none_or(|b| *b.start() == 0) {
+            return;
+        }
+        let mut positions = self.caret_line_positions();
+        for b in blocks {
+            let mut lines: Vec<String> = b.clone().map(|l| self.line_content(l)).collect();
+            lines.push(self.line_content(b.start() - 1));
+            self.replace_lines(b.start() - 1..=*b.end(), &lines, line_feed);
+        }
+        for (index, selection, _) in positions.iter_mut() {
+            index.0 -= 1;
+            selection.0 -= 1;
+        }
+        self.set_caret_line_positions(&positions);
+    }
+
+    /// Move the lines of each caret below the next line
+    pub fn move_lines_down(&mut self, line_feed: LineFeed) {
+        let blocks = self.caret_line_blocks();
+        if blocks.last().is_none_or(|b| *b.end() + 1 >= self.len_lines()) {
+            return;
+        }
+        let mut positions = self.caret_line_positions();
+        for b in blocks {
+            let mut lines = vec![self.line_content(b.end() + 1)];
+            lines.extend(b.clone().map(|l| self.line_content(l)));
+            self.replace_lines(*b.start()..=b.end() + 1, &lines, line_feed);
+        }
+        for (index, selection, _) in positions.iter_mut() {
+            index.0 += 1;
+            selection.0 += 1;
+        }
+        self.set_caret_line_positions(&positions);
+    }
+
+    /// Copy the lines of each caret below them, the carets go to the copy
+    pub fn duplicate_lines(&mut self, line_feed: LineFeed) {
+        let blocks = self.caret_line_blocks();
+        let mut positions = self.caret_line_positions();
+        for b in blocks.iter().rev() {
+            let mut lines: Vec<String> = b.clone().map(|l| self.line_content(l)).collect();
+            lines.extend_from_slice(&lines.clone());
+            self.replace_lines(b.clone(), &lines, line_feed);
+        }
+        let mut added = 0;
+        for b in blocks.iter() {
+            let len = b.end() - b.start() + 1;
+            for (index, selection, _) in positions.iter_mut() {
+                if b.contains(&index.0) {
+                    index.0 += added + len;
+                }
+                if b.contains(&selection.0) {
+                    selection.0 += added + len;
+                }
+            }
+            added += len;
+        }
+        self.set_caret_line_positions(&positions);
+    }
+
+    /// Delete the lines of each caret, with their line feed
+    pub fn delete_lines(&mut self) {
+        let blocks = self.caret_line_blocks();
+        let mut positions = self.caret_line_positions();
+        for b in blocks.iter().rev() {
+            let range = self.line_block_range(b.clone());
+            self.edit(&range, "");
+        }
+        let mut removed = 0;
+        for b in blocks.iter() {
+            // a selection ending at the start of a line keeps this line, so a caret is found by its first line
+            for (index, selection, _) in positions.iter_mut().filter(|p| b.contains(&p.0 .0.min(p.1 .0))) {
+                *index = (b.start() - removed, index.1);
+                *selection = *index;
+            }
+            removed += b.end() - b.start() + 1;
+        }
+        self.set_caret_line_positions(&positions);
+    }
+
+    /// Join each line of a selection, or the line of a caret and the next one.
+    /// The whitespaces around each join are replaced by a single space.
+    pub fn join_lines(&mut self, line_feed: LineFeed) {
+        let mut blocks: Vec<(RangeInclusive<usize>, bool)> = self
+            .caret_line_blocks()
+            .into_iter()
+            .map(|b| {
+                if b.start() == b.end() {
+                    (*b.start()..=(b.end() + 1).min(self.len_lines() - 1), false)
+                } else {
+                    (b, true)
+                }
+            })
+            .collect();
+        blocks.dedup_by(|b, a| {
+            if b.0.start() <= a.0.end() {
+                a.0 = *a.0.start()..=*b.0.end().max(a.0.end());
+                a.1 |= b.1;
+                true
+            } else {
+                false
+            }
+        });
+
+        let mut joins = Vec::new();
+        for (b, _) in blocks.iter().rev() {
+            let mut joined = self.line_content(*b.start());
+            let mut join_point = None;
+            for l in b.start() + 1..=*b.end() {
+                let content = self.line_content(l);
+                let next = content.trim_start();
+                joined.truncate(joined.trim_end().len());
+                join_point.get_or_insert(joined.len());
+                if !next.is_empty() && !joined.is_empty() {
+                    joined.push(' ');
+                }
+                joined.push_str(next);
+            }
+            joins.push((joined.len(), join_point));
+            self.replace_lines(b.clone(), &[joined], line_feed);
+        }
+        joins.reverse();
+
+        let mut positions = Vec::new();
+        let mut removed = 0;
+        for ((b, selection), (len, join_point)) in blocks.iter().zip(joins) {
+            let line = b.start() - removed;
+            if *selection {
+                positions.push(((line, len), (line, 0), true));
+            } else {
+                let offset = join_point.unwrap_or(len);
+                positions.push(((line, offset), (line, offset), true));
+            }
+            removed += b.end() - b.start();
+        }
+        if let Some(p) = positions.last_mut() {
+            p.2 = false;
+        }
+        self.set_caret_line_positions(&positions);
+    }
+
+    /// Insert an empty line, indented like the current one, below the lines of each caret
+    pub fn insert_line_below(&mut self, line_feed: LineFeed) {
+        self.insert_empty_lines(false, line_feed);
+    }
+
+    /// Insert an empty line, indented like the current one, above the lines of each caret
+    pub fn insert_line_above(&mut self, line_feed: LineFeed) {
+        self.insert_empty_lines(true, line_feed);
+    }
+
+    fn insert_empty_lines(&mut self, above: bool, line_feed: LineFeed) {
+        let blocks = self.caret_line_blocks();
+        let mut positions = Vec::new();
+        for b in blocks.iter().rev() {
+            let line = if above { *b.start() } else { *b.end() };
+            let indentation = {
+                let line = Line::from(line);
+                self.slice(line.start(self)..line.absolute_indentation(self))
+                    .to_string()
+            };
+            let line = Line::from(line);
+            if above {
+                let at = line.start(self);
+                self.edit(&(at..at), &format!("{}{}", indentation, line_feed.to_str()));
+            } else {
+                let at = line.end(self);
+                self.edit(&(at..at), &format!("{}{}", line_feed.to_str(), indentation));
+            }
+            positions.push((*b.start(), *b.end(), indentation.len()));
+        }
+        positions.reverse();
+
+        let mut carets = Vec::new();
+        for (added, (first, last, indentation)) in positions.into_iter().enumerate() {
+            let line = if above { first + added } else { last + added + 1 };
+            carets.push(((line, indentation), (line, indentation), true));
+        }
+        if let Some(c) = carets.last_mut() {
+            c.2 = false;
+        }
+        self.set_caret_line_positions(&carets);
+    }
+
+    /// Reorder the selected lines, each selection on its own, or the whole buffer when nothing is selected
+    pub fn sort_lines(&mut self, order: LineOrder, line_feed: LineFeed) {
+        self.rewrite_lines(line_feed, |buffer, block| {
+            let mut lines: Vec<String> = block.map(|l| buffer.line_content(l)).collect();
+            order.apply(&mut lines);
+            lines
+        });
+    }
+
+    /// Keep only the selected lines, or the lines of the whole buffer, matching `matcher`, or only
+    /// the ones not matching it when `keep` is false. Returns the number of removed lines.
+    pub fn filter_lines(&mut self, matcher: &Matcher, keep: bool, line_feed: LineFeed) -> usize {
+        let mut removed = 0;
+        self.rewrite_lines(line_feed, |buffer, block| {
+            let len = block.end() - block.start() + 1;
+            let lines: Vec<String> = buffer
+                .matching_lines(matcher, block, keep)
+                .map(|l| buffer.line_content(l.index))
+                .collect();
+            removed += len - lines.len();
+            lines
+        });
+        removed
+    }
+
+    /// The selected lines, or the lines of the whole buffer, matching `matcher`
+    pub fn lines_matching(&self, matcher: &Matcher) -> Vec<String> {
+        let (blocks, _) = self.selected_line_blocks();
+        blocks
+            .into_iter()
+            .flat_map(|b| self.matching_lines(matcher, b, true).collect::<Vec<_>>())
+            .map(|l| self.line_content(l.index))
+            .collect()
+    }
+
+    fn matching_lines<'a>(
+        &'a self,
+        matcher: &'a Matcher,
+        block: RangeInclusive<usize>,
+        matching: bool,
+    ) -> impl Iterator<Item = Line> + 'a {
+        self.line(*block.start())
+            .iter(self)
+            .take(block.end() - block.start() + 1)
+            .filter(move |l| {
+                matcher
+                    .find_iter(&self.rope, l.start(self)..l.end(self))
+                    .next()
+                    .is_some()
+                    == matching
+            })
+    }
+
+    /// The line blocks of the selections, or the whole buffer when nothing is selected, with a flag
+    /// telling which one it is
+    fn selected_line_blocks(&self) -> (Vec<RangeInclusive<usize>>, bool) {
+        if self.carets.iter().all(|c| c.selection_is_empty()) {
+            let mut last = self.len_lines() - 1;
+            // the empty line after the final line feed is not part of the content
+            if last > 0 && self.line_content(last).is_empty() {
+                last -= 1;
+            }
+            (vec![0..=last], true)
+        } else {
+            (self.caret_line_blocks(), false)
+        }
+    }
+
+    /// Replace each block of `selected_line_blocks` by the lines built by `f`. The new blocks are selected.
+    fn rewrite_lines<F>(&mut self, line_feed: LineFeed, mut f: F)
+    where
+        F: FnMut(&Self, RangeInclusive<usize>) -> Vec<String>,
+    {
+        let (blocks, whole_buffer) = self.selected_line_blocks();
+        let mut positions = self.caret_line_positions();
+
+        let mut rewritten = Vec::new();
+        for b in blocks.iter().rev() {
+            let new_lines = f(self, b.clone());
+            if new_lines.is_empty() {
+                let range = self.line_block_range(b.clone());
+                self.edit(&range, "");
+            } else if b.clone().map(|l| self.line_content(l)).ne(new_lines.iter().cloned()) {
+                self.replace_lines(b.clone(), &new_lines, line_feed);
+            }
+            rewritten.push((new_lines.len(), new_lines.last().map_or(0, |l| l.len())));
+        }
+        rewritten.reverse();
+
+        if !whole_buffer {
+            positions.clear();
+            let mut removed = 0;
+            for (b, (len, last_len)) in blocks.iter().zip(rewritten) {
+                let first = b.start() - removed;
+                if len == 0 {
+                    positions.push(((first, 0), (first, 0), true));
+                } else {
+                    positions.push(((first + len - 1, last_len), (first, 0), true));
+                }
+                removed += b.end() - b.start() + 1 - len;
+            }
+            if let Some(p) = positions.last_mut() {
+                p.2 = false;
+            }
+        }
+        self.set_caret_line_positions(&positions);
+    }
+}
+
+/// Merge sorted blocks overlapping or separated by less than `gap` lines
+fn merge_blocks(blocks: Vec<RangeInclusive<usize>>, gap: usize) -> Vec<RangeInclusive<usize>> {
+    let mut merged: Vec<RangeInclusive<usize>> = Vec::new();
+    for b in blocks {
+        match merged.last_mut() {
+            Some(last) if *b.start() <= last.end() + gap => {
+                *last = *last.start()..=*b.end().max(last.end());
+            }
+            _ => merged.push(b),
+        }
+    }
+    merged
+}
+
+/// Compare two strings, the runs of digits being compared by their numeric value
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                let (tx, ty) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = tx
+                    .len()
+                    .cmp(&ty.len())
+                    .then_with(|| tx.cmp(ty))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        number.push(c);
+    }
+    number
+}
+
+/// Fisher-Yates shuffle, with a xorshift generator seeded by the std random hasher keys
+fn shuffle<T>(items: &mut [T]) {
+    let mut state = RandomState::new().build_hasher().finish() | 1;
+    for i in (1..items.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        items.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn move_and_duplicate() {
+        let mut b = Buffer::with_caret("a\nb\nc", 2..2);
+        b.move_lines_up(LineFeed::Lf);
+        assert_eq!(b.rope.to_string(), "b\na\nc");
+        assert_eq!(b.main_caret().index, Absolute::from(0));
+        b.move_lines_down(LineFeed::Lf);
+        b.move_lines_down(LineFeed::Lf);
+        assert_eq!(b.rope.to_string(), "a\nc\nb");
+        b.duplicate_lines(LineFeed::Lf);
+        assert_eq!(b.rope.to_string(), "a\nc\nb\nb");
+        assert_eq!(b.main_caret().index, Absolute::from(6));
+    }
+
+    #[test]
+    fn delete_and_join() {
+        let mut b = Buffer::with_caret("a\nb\nc", 4..4);
+        b.delete_lines();
+        assert_eq!(b.rope.to_string(), "a\nb");
+        let mut b = Buffer::with_caret("a\nb\nc\nd\ne\nf", 2..6);
+        b.delete_lines();
+        assert_eq!(b.rope.to_string(), "a\nd\ne\nf");
+        assert_eq!(b.main_caret().range(), Absolute::from(2)..Absolute::from(2));
+        let mut b = Buffer::with_caret("  a  \n   b\nc", 0..0);
+        b.join_lines(LineFeed::Lf);
+        assert_eq!(b.rope.to_string(), "  a b\nc");
+        assert_eq!(b.main_caret().index, Absolute::from(3));
+    }
+
+    #[test]
+    fn sort() {
+        let mut b = Buffer::with_caret("b10\nB2\na\nb2\nb10\n", 0..0);
+        b.sort_lines(LineOrder::Natural, LineFeed::Lf);
+        assert_eq!(b.rope.to_string(), "B2\na\nb2\nb10\nb10\n");
+        b.sort_lines(LineOrder::Unique, LineFeed::Lf);
+        assert_eq!(b.rope.to_string(), "B2\na\nb2\nb10\n");
+        b.sort_lines(LineOrder::CaseInsensitive, LineFeed::Lf);
+        assert_eq!(b.rope.to_string(), "a\nb10\nB2\nb2\n");
+        b.sort_lines(LineOrder::Reverse, LineFeed::Lf);
+        assert_eq!(b.rope.to_string(), "b2\nB2\nb10\na\n");
+    }
+
+    #[test]
+    fn filter() {
+        use super::super::search::SearchQuery;
+
+        let mut b = Buffer::with_caret("error 1\ninfo\nerror 2\n", 0..0);
+        let matcher = Matcher::new(&SearchQuery {
+            pattern: "^error".to_owned(),
+            regex: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(b.lines_matching(&matcher), vec!["error 1", "error 2"]);
+        assert_eq!(b.filter_lines(&matcher, false, LineFeed::Lf), 2);
+        assert_eq!(b.rope.to_string(), "info\n");
+        assert_eq!(b.filter_lines(&matcher, true, LineFeed::Lf), 1);
+        assert_eq!(b.rope.to_string(), "");
+    }
+}
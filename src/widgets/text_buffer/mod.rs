@@ -1,11 +1,27 @@
+mod align;
 pub mod buffer;
 mod caret;
+mod case;
+mod comment;
 mod edit_stack;
 mod file;
+mod indent;
+mod line_ops;
+mod numbers;
+mod pairs;
+mod surround;
+mod tags;
+mod transform;
 pub mod position;
 pub mod rope_utils;
+pub mod search;
 pub mod syntax;
+mod undo;
 
+pub use case::Case;
 pub use edit_stack::*;
-pub use file::Indentation;
+pub use file::{Indentation, TextFileInfo};
+pub use line_ops::LineOrder;
+pub use numbers::Sequence;
+pub use transform::Transform;
 
@@ -0,0 +1,311 @@
+use std::ops::Range;
+
+use super::buffer::Buffer;
+
+/// The numbers inserted by `Buffer::insert_sequence`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sequence {
+    start: i64,
+    step: i64,
+    /// The minimal count of digits, padded with zeros
+    width: usize,
+    hex: bool,
+}
+
+impl Sequence {
+    /// Parse a sequence typed by the user: a start and an optional step, `1` by default.
+    /// Leading zeros in the start give the zero padding, a `0x` prefix gives hexadecimal numbers.
+    pub fn parse(input: &str) -> Option<Self> {
+        let mut parts = input.split_whitespace();
+        let start = parts.next()?;
+        let step = parts.next().map_or(Some(1), |s| s.parse().ok())?;
+        if parts.next().is_some() {
+            return None;
+        }
+        let (negative, digits) = match start.strip_prefix('-') {
+            Some(d) => (true, d),
+            None => (false, start),
+        };
+        let (hex, digits) = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+            Some(d) => (true, d),
+            None => (false, digits),
+        };
+        let value = i64::from_str_radix(digits, if hex { 16 } else { 10 }).ok()?;
+        Some(Sequence {
+            start: if negative { -value } else { value },
+            step,
+            width: if hex || digits.starts_with('0') {
+                digits.len()
+            } else {
+                0
+            },
+            hex,
+        })
+    }
+
+    /// The number at the position `n` of the sequence
+    pub fn nth(&self, n: usize) -> String {
+        let value = self.start + self.step * n as i64;
+        let sign = if value < 0 { "-" } else { "" };
+        if self.hex {
+            format!("{}0x{:0width$x}", sign, value.unsigned_abs(), width = self.width)
+        } else {
+            format!("{}{:0width$}", sign, value.unsigned_abs(), width = self.width)
+        }
+    }
+}
+
+/// A number of a line, as found by `number_at`
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Number {
+    Decimal(i64),
+    /// The value, and whether the digits are in upper case
+    Hex(u64, bool),
+    /// A `YYYY-MM-DD` date and the component to change: 0 for the year, 1 for the month, 2 for the day
+    Date([i64; 3], usize),
+}
+
+/// The number of `line` around the byte `offset`, with its byte range in the line, and, for a date,
+/// the range of the changed component
+fn number_at(line: &str, offset: usize) -> Option<(Number, Range<usize>, Range<usize>)> {
+    let bytes = line.as_bytes();
+    let offset = offset.min(line.len());
+
+    // a date containing the offset
+    for date_start in offset.saturating_sub(10)..=offset {
+        if let Some(date) = line.get(date_start..date_start + 10) {
+            let parts: Vec<&str> = date.split('-').collect();
+            if parts.len() == 3
+                && [4, 2, 2]
+                    .iter()
+                    .zip(&parts)
+                    .all(|(l, p)| p.len() == *l && p.bytes().all(|b| b.is_ascii_digit()))
+            {
+                let values = [parts[0].parse().ok()?, parts[1].parse().ok()?, parts[2].parse().ok()?];
+                let component = match offset - date_start {
+                    0..=4 => 0,
+                    5..=7 => 1,
+                    _ => 2,
+                };
+                let component_range = [0..4, 5..7, 8..10][component].clone();
+                let component_range = date_start + component_range.start..date_start + component_range.end;
+                return Some((
+                    Number::Date(values, component),
+                    date_start..date_start + 10,
+                    component_range,
+                ));
+            }
+        }
+    }
+
+    // a hex number in the word of the offset
+    let is_alnum = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_alphanumeric);
+    let mut start = offset;
+    while start > 0 && is_alnum(start - 1) {
+        start -= 1;
+    }
+    let mut end = offset;
+    while is_alnum(end) {
+        end += 1;
+    }
+    let word = &line[start..end];
+    for (i, _) in word.match_indices("0x") {
+        let digits_start = start + i + 2;
+        let digits_end = digits_start + line[digits_start..].bytes().take_while(u8::is_ascii_hexdigit).count();
+        if digits_end > digits_start && start + i <= offset && offset <= digits_end {
+            let digits = &line[digits_start..digits_end];
+            let value = u64::from_str_radix(digits, 16).ok()?;
+            let upper = digits.bytes().any(|b| b.is_ascii_uppercase());
+            return Some((
+                Number::Hex(value, upper),
+                digits_start..digits_end,
+                digits_start..digits_end,
+            ));
+        }
+    }
+
+    // a decimal number, the digits touching the offset
+    let mut digits_start = offset;
+    while digits_start > 0 && bytes[digits_start - 1].is_ascii_digit() {
+        digits_start -= 1;
+    }
+    let mut digits_end = offset;
+    while bytes.get(digits_end).is_some_and(u8::is_ascii_digit) {
+        digits_end += 1;
+    }
+    if digits_start == digits_end {
+        return None;
+    }
+    // a minus sign not preceded by a word char
+    if digits_start > 0
+        && bytes[digits_start - 1] == b'-'
+        && !(digits_start > 1 && (bytes[digits_start - 2].is_ascii_alphanumeric() || bytes[digits_start - 2] == b'_'))
+    {
+        digits_start -= 1;
+    }
+    let value = line[digits_start..digits_end].parse().ok()?;
+    Some((
+        Number::Decimal(value),
+        digits_start..digits_end,
+        digits_start..digits_end,
+    ))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// The days since 1970-01-01 of a date
+fn days_from_civil([y, m, d]: [i64; 3]) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> [i64; 3] {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    [yoe + era * 400 + if m <= 2 { 1 } else { 0 }, m, d]
+}
+
+impl Number {
+    /// The text of the number changed by `delta`, keeping its format
+    fn add(&self, delta: i64, original: &str) -> String {
+        match self {
+            Number::Decimal(v) => {
+                let value = v.saturating_add(delta);
+                let digits = original.trim_start_matches('-');
+                let width = if digits.len() > 1 && digits.starts_with('0') {
+                    digits.len()
+                } else {
+                    0
+                };
+                let sign = if value < 0 { "-" } else { "" };
+                format!("{}{:0width$}", sign, value.unsigned_abs(), width = width)
+            }
+            Number::Hex(v, upper) => {
+                let value = (*v as i64).wrapping_add(delta) as u64;
+                let width = original.len();
+                if *upper {
+                    format!("{:0width$X}", value, width = width)
+                } else {
+                    format!("{:0width$x}", value, width = width)
+                }
+            }
+            Number::Date([y, m, d], component) => {
+                let [y, m, d] = match component {
+                    0 => [y + delta, *m, *d.min(&days_in_month(y + delta, *m))],
+                    1 => {
+                        let months = y * 12 + m - 1 + delta;
+                        let (y, m) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+                        [y, m, *d.min(&days_in_month(y, m))]
+                    }
+                    _ => civil_from_days(days_from_civil([*y, *m, *d]) + delta),
+                };
+                format!("{:04}-{:02}-{:02}", y, m, d)
+            }
+        }
+    }
+}
+
+impl Buffer {
+    /// The caret indices, in the document order of the carets
+    fn carets_in_order(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.carets.len()).collect();
+        indices.sort_by(|a, b| self.carets[*a].cmp(&self.carets[*b]));
+        indices
+    }
+
+    /// Replace the selection of each caret by the next number of `sequence`, in document order
+    pub fn insert_sequence(&mut self, sequence: &Sequence) {
+        for (n, i) in self.carets_in_order().into_iter().enumerate().rev() {
+            self.fill_virtual_space(i);
+            let r = self.carets[i].range();
+            let text = sequence.nth(n);
+            self.edit(&r, &text);
+            self.set_caret_index(i, r.start + text.len(), true, true);
+        }
+        self.carets.merge();
+    }
+
+    /// Add `delta` to the number, hexadecimal number or date component under each caret.
+    /// Returns false if there is no number under any caret.
+    pub fn increment_numbers(&mut self, delta: i64) -> bool {
+        let mut changed = false;
+        for i in self.carets_in_order().into_iter().rev() {
+            let line = self.carets[i].line();
+            let start = line.start(self);
+            let text = self.slice(start..line.end(self)).to_string();
+            let offset = self.carets[i].index.index - start.index;
+            let (number, range, changed_range) = match number_at(&text, offset) {
+                Some(n) => n,
+                None => continue,
+            };
+            let new = number.add(delta, &text[range.clone()]);
+            let caret = match number {
+                // the caret stays at the end of the changed component
+                Number::Date(..) => start + changed_range.end,
+                _ => start + range.start + new.len(),
+            };
+            self.edit(&(start + range.start..start + range.end), &new);
+            self.set_caret_index(i, caret, true, true);
+            changed = true;
+        }
+        self.carets.merge();
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::widgets::text_buffer::position::{Column, Line};
+
+    #[test]
+    fn sequences() {
+        let s = Sequence::parse("08 2").unwrap();
+        assert_eq!((s.nth(0), s.nth(1)), ("08".to_owned(), "10".to_owned()));
+        let s = Sequence::parse("0x0e").unwrap();
+        assert_eq!((s.nth(0), s.nth(2)), ("0x0e".to_owned(), "0x10".to_owned()));
+        assert_eq!(Sequence::parse("1 -1").unwrap().nth(3), "-2");
+        assert!(Sequence::parse("a").is_none());
+
+        let mut b = Buffer::from_rope("a\nb\nc".into(), 4);
+        b.select_block((Line::from(0), Column::from(1)), (Line::from(2), Column::from(1)));
+        b.insert_sequence(&Sequence::parse("1").unwrap());
+        assert_eq!(b.rope.to_string(), "a1\nb2\nc3");
+    }
+
+    #[test]
+    fn increments() {
+        let change = |line: &str, offset: usize, delta: i64| {
+            let (n, r, _) = number_at(line, offset).unwrap();
+            let mut result = line.to_owned();
+            result.replace_range(r.clone(), &n.add(delta, &line[r]));
+            result
+        };
+        assert_eq!(change("x = 9;", 5, 1), "x = 10;");
+        assert_eq!(change("x = 0;", 5, -1), "x = -1;");
+        assert_eq!(change("v007", 2, 1), "v008");
+        assert_eq!(change("0xFF", 1, 1), "0x100");
+        assert_eq!(change("0x0f", 4, 1), "0x10");
+        assert_eq!(change("on 2024-02-28.", 13, 1), "on 2024-02-29.");
+        assert_eq!(change("on 2023-02-28.", 13, 1), "on 2023-03-01.");
+        assert_eq!(change("2024-01-31", 6, 1), "2024-02-29");
+        assert_eq!(change("2024-12-31", 2, -1), "2023-12-31");
+    }
+}
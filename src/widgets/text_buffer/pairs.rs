@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use once_cell::sync::Lazy;
+
+use super::buffer::Buffer;
+use super::position::Absolute;
+
+/// An opening character and the one closing it
+pub type Pair = (char, char);
+
+/// The pairs of the languages not in `LANGUAGE_PAIRS`
+const DEFAULT_PAIRS: &[Pair] = &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')];
+
+/// The pairs of each language, by syntax name
+static LANGUAGE_PAIRS: Lazy<HashMap<&str, Vec<Pair>>> = Lazy::new(|| {
+    let brackets_and_quotes = vec![('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+    let mut m = HashMap::new();
+    // no single quote, it starts a lifetime
+    m.insert("Rust", DEFAULT_PAIRS.to_vec());
+    m.insert("Plain Text", vec![('(', ')'), ('[', ']'), ('{', '}')]);
+    for name in [
+        "Python",
+        "Ruby",
+        "PHP",
+        "Bourne Again Shell (bash)",
+        "C",
+        "C++",
+        "C#",
+        "Java",
+        "Go",
+    ] {
+        m.insert(name, brackets_and_quotes.clone());
+    }
+    for name in ["JavaScript", "TypeScript", "Markdown"] {
+        let mut pairs = brackets_and_quotes.clone();
+        pairs.push(('`', '`'));
+        m.insert(name, pairs);
+    }
+    for name in ["HTML", "XML"] {
+        let mut pairs = brackets_and_quotes.clone();
+        pairs.push(('<', '>'));
+        m.insert(name, pairs);
+    }
+    m
+});
+
+/// The auto closed pairs of the language `syntax_name`
+pub fn language_pairs(syntax_name: &str) -> &'static [Pair] {
+    LANGUAGE_PAIRS.get(syntax_name).map_or(DEFAULT_PAIRS, |p| p.as_slice())
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Buffer {
+    /// Type `c` at each caret, with the auto pairing of `pairs`:
+    /// - a closing char just before the same char, auto closed by a previous call, steps over it
+    /// - an opening char surrounds the selection, if any
+    /// - an opening char inserts the whole pair, unless the caret is in a string or a comment (`in_literal`),
+    ///   is before a word char, or, for a quote, after a word char
+    pub fn type_char(&mut self, c: char, pairs: &[Pair], in_literal: impl Fn(Absolute) -> bool) {
+        // the positions are checked before any edit
+        let literals: Vec<bool> = self.carets.iter().map(|c| in_literal(c.start())).collect();
+        self.forget_left_pairs();
+        for (i, literal) in literals.into_iter().enumerate() {
+            self.fill_virtual_space(i);
+            let r = self.carets[i].range();
+            let next = self.char_at(r.end);
+            let prev = self.char_before(r.start);
+
+            if r.start == r.end && next == Some(c) && self.auto_closed.iter().any(|p| p.end == r.end) {
+                self.set_caret_index(i, r.start + c.len_utf8(), true, true);
+                continue;
+            }
+            match pairs.iter().find(|p| p.0 == c) {
+                Some(&(open, close)) if r.start != r.end => {
+                    self.edit(&(r.end..r.end), &close.to_string());
+                    self.edit(&(r.start..r.start), &open.to_string());
+                    self.set_caret_index(i, r.start + open.len_utf8(), true, true);
+                    self.set_caret_index(i, r.end + open.len_utf8(), false, true);
+                }
+                Some(&(open, close))
+                    if !literal
+                        && !next.is_some_and(is_word_char)
+                        && (open != close || !prev.is_some_and(is_word_char)) =>
+                {
+                    self.edit(&r, &format!("{}{}", open, close));
+                    self.set_caret_index(i, r.start + open.len_utf8(), true, true);
+                    self.auto_closed.push(r.start..r.start + open.len_utf8());
+                }
+                _ => {
+                    self.edit(&r, &c.to_string());
+                    self.set_caret_index(i, r.start + c.len_utf8(), true, true);
+                }
+            }
+        }
+        self.carets.merge();
+        self.forget_left_pairs();
+    }
+
+    /// Move the auto closed pairs after the edit replacing `range` by `inserted` bytes.
+    /// A pair losing one of its chars is forgotten.
+    pub(super) fn update_auto_closed(&mut self, range: &Range<Absolute>, inserted: usize) {
+        let removed = range.end.index - range.start.index;
+        let moved = |a: Absolute| {
+            if a < range.start {
+                Some(a)
+            } else if a >= range.end {
+                Some(Absolute::from(a.index - removed + inserted))
+            } else {
+                None
+            }
+        };
+        self.auto_closed = self
+            .auto_closed
+            .iter()
+            .filter_map(|p| Some(moved(p.start)?..moved(p.end)?))
+            .collect();
+    }
+
+    /// Forget the auto closed pairs no caret is in anymore, so their closing char is not stepped over
+    pub(super) fn forget_left_pairs(&mut self) {
+        let carets = &self.carets;
+        self.auto_closed
+            .retain(|p| carets.iter().any(|c| p.start < c.index && c.index <= p.end));
+    }
+
+    /// If the caret `i` is between the two chars of an empty pair, the range of the pair
+    pub(super) fn empty_pair_around(&self, i: usize, pairs: &[Pair]) -> Option<Range<Absolute>> {
+        let index = self.carets[i].index;
+        if !self.carets[i].selection_is_empty() {
+            return None;
+        }
+        let prev = self.char_before(index)?;
+        let next = self.char_at(index)?;
+        pairs
+            .contains(&(prev, next))
+            .then_some(index - prev.len_utf8()..index + next.len_utf8())
+    }
+
+    fn char_at(&self, a: Absolute) -> Option<char> {
+        self.rope.get_char(self.rope.byte_to_char(a.index))
+    }
+
+    fn char_before(&self, a: Absolute) -> Option<char> {
+        self.rope.chars_at(self.rope.byte_to_char(a.index)).prev()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_pairs() {
+        let mut b = Buffer::from_rope("".into(), 4);
+        b.type_char('(', DEFAULT_PAIRS, |_| false);
+        b.type_char(')', DEFAULT_PAIRS, |_| false);
+        assert_eq!(b.rope.to_string(), "()");
+        assert_eq!(b.main_caret().index, Absolute::from(2));
+        b.type_char('a', DEFAULT_PAIRS, |_| false);
+        b.type_char('"', DEFAULT_PAIRS, |_| false);
+        assert_eq!(b.rope.to_string(), "()a\"");
+        b.type_char('[', DEFAULT_PAIRS, |_| true);
+        assert_eq!(b.rope.to_string(), "()a\"[");
+        b.type_char('{', DEFAULT_PAIRS, |_| false);
+        assert_eq!(
+            b.empty_pair_around(0, DEFAULT_PAIRS),
+            Some(Absolute::from(5)..Absolute::from(7))
+        );
+    }
+
+    #[test]
+    fn overtype_only_auto_closed() {
+        let mut b = Buffer::from_rope(")".into(), 4);
+        b.type_char(')', DEFAULT_PAIRS, |_| false);
+        assert_eq!(b.rope.to_string(), "))");
+
+        let mut b = Buffer::with_caret("x = \"a\"", 4..4);
+        b.type_char('"', DEFAULT_PAIRS, |_| false);
+        assert_eq!(b.rope.to_string(), "x = \"\"\"a\"");
+
+        // the pair is forgotten once the caret leaves it
+        let mut b = Buffer::from_rope("".into(), 4);
+        b.type_char('(', DEFAULT_PAIRS, |_| false);
+        b.type_char('(', DEFAULT_PAIRS, |_| false);
+        b.forward(false, false);
+        b.forward(false, false);
+        b.backward(false, false);
+        b.type_char(')', DEFAULT_PAIRS, |_| false);
+        assert_eq!(b.rope.to_string(), "(()))");
+    }
+}
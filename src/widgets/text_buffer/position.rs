@@ -399,19 +399,25 @@ impl Line {
     }
 
     pub fn iter<'r>(&self, buffer: &'r Buffer) -> LineIterator<'r> {
-        LineIterator { buffer, line: *self }
+        LineIterator {
+            buffer,
+            line: Some(*self),
+        }
     }
 }
 
+/// Iterate over the lines of a buffer, from a given line to the last one
 pub struct LineIterator<'r> {
     buffer: &'r Buffer,
-    line: Line,
+    line: Option<Line>,
 }
 
 impl<'r> Iterator for LineIterator<'r> {
     type Item = Line;
     fn next(&mut self) -> Option<Self::Item> {
-        self.line.next(self.buffer)
+        let line = self.line?;
+        self.line = line.next(self.buffer);
+        Some(line)
     }
 }
 
@@ -207,6 +207,87 @@ pub fn word_end<U: Into<usize>>(slice: &RopeSlice, byte_idx: U) -> usize {
     slice.char_to_byte(i)
 }
 
+/// Check if the given byte position is between two chars of different type, or at a bound of the slice
+pub fn is_word_boundary<U: Into<usize>>(slice: &RopeSlice, byte_idx: U) -> bool {
+    let i = slice.byte_to_char(byte_idx.into());
+    if i == 0 || i >= slice.len_chars() {
+        return true;
+    }
+    is_boundary(slice.char(i - 1), slice.char(i))
+}
+
+/// The byte range of the word around `byte_idx`, a run of chars of the `CharType::Other` type,
+/// preferring the word after the position. Empty if there is no word on either side.
+pub fn word_range<U: Into<usize>>(slice: &RopeSlice, byte_idx: U) -> std::ops::Range<usize> {
+    let i = slice.byte_to_char(byte_idx.into());
+    let is_word = |c: char| char_type(c) == CharType::Other;
+    let mut start = i;
+    if !(slice.get_char(i).is_some_and(is_word) || i > 0 && is_word(slice.char(i - 1))) {
+        return slice.char_to_byte(i)..slice.char_to_byte(i);
+    }
+    let mut before = slice.chars_at(i);
+    while before.prev().is_some_and(is_word) {
+        start -= 1;
+    }
+    let end = i + slice.chars_at(i).take_while(|c| is_word(*c)).count();
+    slice.char_to_byte(start)..slice.char_to_byte(end)
+}
+
+/// The byte ranges of the words of `text`: the runs of `CharType::Other` chars, split at the underscores
+/// and at the case changes, so `parseHTTPResponse_code` gives `parse`, `HTTP`, `Response` and `code`
+pub fn split_words(text: &str) -> Vec<std::ops::Range<usize>> {
+    let mut words = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut chars = text.char_indices().peekable();
+    let mut prev: Option<char> = None;
+    while let Some((i, c)) = chars.next() {
+        if char_type(c) != CharType::Other || c == '_' {
+            if let Some(s) = start.take() {
+                words.push(s..i);
+            }
+        } else {
+            let next = chars.peek().map(|n| n.1);
+            // a lower case then an upper case, or the last upper case of an acronym before a lower case
+            let hump = prev.is_some_and(|p| p.is_lowercase() || p.is_numeric()) && c.is_uppercase()
+                || prev.is_some_and(char::is_uppercase) && c.is_uppercase() && next.is_some_and(char::is_lowercase);
+            match start {
+                Some(s) if hump => {
+                    words.push(s..i);
+                    start = Some(i);
+                }
+                None => start = Some(i),
+                _ => (),
+            }
+        }
+        prev = Some(c);
+    }
+    if let Some(s) = start {
+        words.push(s..text.len());
+    }
+    words
+}
+
+/// The byte ranges of the identifiers of `text`: the runs of `CharType::Other` chars, joined by the `-`
+/// between two of them, so `max-size` is one identifier and `a - b` two
+pub fn identifier_ranges(text: &str) -> Vec<std::ops::Range<usize>> {
+    let mut identifiers: Vec<std::ops::Range<usize>> = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    let mut prev: Option<char> = None;
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|n| n.1);
+        let joins = c == '-'
+            && prev.is_some_and(|p| char_type(p) == CharType::Other)
+            && next.is_some_and(|n| char_type(n) == CharType::Other);
+        if char_type(c) == CharType::Other || joins {
+            match identifiers.last_mut() {
+                Some(last) if last.end == i => last.end = i + c.len_utf8(),
+                _ => identifiers.push(i..i + c.len_utf8()),
+            }
+        }
+        prev = Some(c);
+    }
+    identifiers
+}
 
 pub fn column_to_relative(col: Column, line: Line, buffer: &Buffer) -> Relative {
     let mut c = 0;
@@ -0,0 +1,252 @@
+use std::{borrow::Cow, ops::Range};
+
+use regex_cursor::{
+    engines::meta::Regex,
+    regex_automata::util::{interpolate, syntax},
+    Input,
+};
+use ropey::Rope;
+
+use super::{position::Absolute, rope_utils};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub pattern: String,
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub wrap_around: bool,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        Self {
+            pattern: String::new(),
+            regex: false,
+            case_sensitive: false,
+            whole_word: false,
+            wrap_around: true,
+        }
+    }
+}
+
+impl SearchQuery {
+    pub fn literal(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pattern.is_empty()
+    }
+}
+
+/// The bytes before the end of the range first searched by `Matcher::rfind`
+const RFIND_WINDOW: usize = 64 * 1024;
+
+/// A compiled `SearchQuery`, searching directly in the rope chunks
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    regex: Regex,
+    whole_word: bool,
+}
+
+impl Matcher {
+    pub fn new(query: &SearchQuery) -> anyhow::Result<Self> {
+        let pattern = if query.regex {
+            Cow::Borrowed(&query.pattern)
+        } else {
+            Cow::Owned(regex_syntax::escape(&query.pattern))
+        };
+        let regex = Regex::builder()
+            .syntax(
+                syntax::Config::new()
+                    .multi_line(true)
+                    .crlf(true)
+                    .case_insensitive(!query.case_sensitive),
+            )
+            .build(&pattern)?;
+        Ok(Self {
+            regex,
+            whole_word: query.whole_word,
+        })
+    }
+
+    /// Find the first match starting in `range`. A match can end after `range.end`.
+    pub fn find(&self, rope: &Rope, range: Range<Absolute>) -> Option<Range<Absolute>> {
+        self.find_iter(rope, range.start..rope.len_bytes().into())
+            .next()
+            .filter(|m| m.start < range.end)
+    }
+
+    /// Find the last match contained in `range`.
+    /// The matches are searched in a window before `range.end`, growing until it has two of them, so the
+    /// last one is not the end of a longer match starting before the window.
+    pub fn rfind(&self, rope: &Rope, range: Range<Absolute>) -> Option<Range<Absolute>> {
+        let mut window = RFIND_WINDOW;
+        loop {
+            let start = range.end.index.saturating_sub(window).max(range.start.index);
+            let start = Absolute::from(rope.char_to_byte(rope.byte_to_char(start)));
+            let (count, last) = self
+                .find_iter(rope, start..range.end)
+                .fold((0, None), |(count, _), m| (count + 1, Some(m)));
+            if count > 1 || start == range.start {
+                return last;
+            }
+            window *= 2;
+        }
+    }
+
+    /// Iterate over all the non overlapping matches contained in `range`.
+    /// For a whole word search, a match not starting and ending at word boundaries is skipped and the search
+    /// restarts one char after its start, so a shorter or overlapping match of a whole word is still found.
+    pub fn find_iter<'a>(
+        &'a self,
+        rope: &'a Rope,
+        range: Range<Absolute>,
+    ) -> impl Iterator<Item = Range<Absolute>> + 'a {
+        let slice = rope.slice(..);
+        let next_char = move |i: usize| {
+            if i < slice.len_bytes() {
+                slice.char_to_byte(slice.byte_to_char(i) + 1)
+            } else {
+                i + 1
+            }
+        };
+        let mut at = range.start.index;
+        let mut last_end = None;
+        std::iter::from_fn(move || {
+            while at <= range.end.index {
+                let m = self.regex.find(Input::new(slice).range(at..range.end.index))?;
+                // like the regex iterator, no empty match right after the previous match
+                if m.is_empty() && last_end == Some(m.end()) {
+                    at = next_char(m.end());
+                    continue;
+                }
+                if self.whole_word
+                    && !(rope_utils::is_word_boundary(&slice, m.start())
+                        && rope_utils::is_word_boundary(&slice, m.end()))
+                {
+                    at = next_char(m.start());
+                    continue;
+                }
+                at = if m.is_empty() { next_char(m.end()) } else { m.end() };
+                last_end = Some(m.end());
+                return Some(Absolute::from(m.start())..Absolute::from(m.end()));
+            }
+            None
+        })
+    }
+
+    /// Check that `range` is exactly a match of this matcher
+    pub fn is_match_at(&self, rope: &Rope, range: Range<Absolute>) -> bool {
+        self.find_iter(rope, range.start..rope.len_bytes().into()).next() == Some(range)
+    }
+
+    /// Build the replacement text for the match at `range`, expanding `$1`, `${name}` and `$$`.
+    pub fn expand(&self, rope: &Rope, range: Range<Absolute>, replacement: &str) -> String {
+        let mut caps = self.regex.create_captures();
+        let input = Input::new(rope.slice(..)).range(range.start.index..rope.len_bytes());
+        self.regex.captures(input, &mut caps);
+
+        let mut dst = String::new();
+        interpolate::string(
+            replacement,
+            |index, dst| {
+                if let Some(span) = caps.get_group(index) {
+                    let slice = rope.byte_slice(span.start..span.end);
+                    for chunk in slice.chunks() {
+                        dst.push_str(chunk);
+                    }
+                }
+            },
+            |name| self.regex.group_info().to_index(Default::default(), name),
+            &mut dst,
+        );
+        dst
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Matcher, SearchQuery, RFIND_WINDOW};
+    use crate::widgets::text_buffer::position::Absolute;
+    use ropey::Rope;
+
+    fn regex(pattern: &str) -> Matcher {
+        Matcher::new(&SearchQuery {
+            pattern: pattern.to_owned(),
+            regex: true,
+            case_sensitive: true,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn multiline_regex() {
+        let rope = Rope::from_str("first line\nsecond line\nthird line\n");
+        let m = regex(r"line\nsecond");
+        assert_eq!(
+            m.find(&rope, Absolute::from(0)..rope.len_bytes().into()),
+            Some(Absolute::from(6)..Absolute::from(17))
+        );
+    }
+
+    #[test]
+    fn expand_captures() {
+        let rope = Rope::from_str("key = value\n");
+        let m = regex(r"(?P<k>\w+) = (\w+)");
+        let r = m.find(&rope, Absolute::from(0)..rope.len_bytes().into()).unwrap();
+        assert_eq!(m.expand(&rope, r, "$2: ${k} $$"), "value: key $");
+    }
+
+    #[test]
+    fn backward_search() {
+        let text = "ab aab ".repeat(RFIND_WINDOW / 4);
+        let rope = Rope::from_str(&text);
+        for pattern in ["a+b", "ab", "b a", r"(?s)a.*"] {
+            let m = regex(pattern);
+            for end in [text.len(), RFIND_WINDOW + 2, 5] {
+                let range = Absolute::from(0)..Absolute::from(end);
+                assert_eq!(m.rfind(&rope, range.clone()), m.find_iter(&rope, range).last());
+            }
+        }
+        assert_eq!(
+            regex("x").rfind(&rope, Absolute::from(0)..rope.len_bytes().into()),
+            None
+        );
+    }
+
+    #[test]
+    fn whole_word() {
+        let rope = Rope::from_str("foobar foo.bar Foo");
+        let m = Matcher::new(&SearchQuery {
+            whole_word: true,
+            ..SearchQuery::literal("foo")
+        })
+        .unwrap();
+        let matches: Vec<_> = m.find_iter(&rope, Absolute::from(0)..rope.len_bytes().into()).collect();
+        assert_eq!(
+            matches,
+            vec![
+                Absolute::from(7)..Absolute::from(10),
+                Absolute::from(15)..Absolute::from(18)
+            ]
+        );
+
+        // the rejected match "o b" overlaps the whole word "bar"
+        let rope = Rope::from_str("foo bar");
+        let m = Matcher::new(&SearchQuery {
+            pattern: "o b|bar".to_owned(),
+            regex: true,
+            whole_word: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let matches: Vec<_> = m.find_iter(&rope, Absolute::from(0)..rope.len_bytes().into()).collect();
+        assert_eq!(matches, vec![Absolute::from(4)..Absolute::from(7)]);
+    }
+}
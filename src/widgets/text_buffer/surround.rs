@@ -0,0 +1,247 @@
+use std::ops::Range;
+
+use super::buffer::Buffer;
+use super::position::Absolute;
+use super::syntax::StyledLine;
+
+const BRACKETS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
+const QUOTES: &[char] = &['"', '\'', '`'];
+
+/// The texts put before and after a selection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Surrounding {
+    open: String,
+    close: String,
+}
+
+impl Surrounding {
+    /// Parse a surrounding typed by the user: a bracket, given by either of its chars, any other char,
+    /// used on both sides, or a tag like `<div class="a">` or `div`
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let mut chars = input.chars();
+        match (chars.next()?, chars.next()) {
+            (c, None) => {
+                let (open, close) = BRACKETS
+                    .iter()
+                    .find(|b| b.0 == c || b.1 == c)
+                    .copied()
+                    .unwrap_or((c, c));
+                Some(Surrounding {
+                    open: open.to_string(),
+                    close: close.to_string(),
+                })
+            }
+            _ => {
+                let tag = input.strip_prefix('<').unwrap_or(input);
+                let tag = tag.strip_suffix('>').unwrap_or(tag).trim();
+                let name = tag_name(tag)?;
+                Some(Surrounding {
+                    open: format!("<{}>", tag),
+                    close: format!("</{}>", name),
+                })
+            }
+        }
+    }
+}
+
+/// The name at the start of a tag content, if it is a valid one
+fn tag_name(tag: &str) -> Option<&str> {
+    let end = tag
+        .find(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')))
+        .unwrap_or(tag.len());
+    let name = &tag[..end];
+    name.chars().next().filter(|c| c.is_alphabetic()).map(|_| name)
+}
+
+/// The ranges of an opening and a closing token
+type PairRanges = (Range<usize>, Range<usize>);
+
+/// The lines searched for the brackets and tags around the carets, before and after them
+const SEARCH_LINES: usize = 1000;
+
+impl Buffer {
+    /// Surround the selection of each caret with `surrounding`, the surrounded text stays selected
+    pub fn surround(&mut self, surrounding: &Surrounding) {
+        for i in 0..self.carets.len() {
+            let r = self.carets[i].range();
+            self.edit(&(r.end..r.end), &surrounding.close);
+            self.edit(&(r.start..r.start), &surrounding.open);
+            self.set_caret_index(i, r.start + surrounding.open.len(), true, true);
+            self.set_caret_index(i, r.end + surrounding.open.len(), false, true);
+        }
+        self.carets.merge();
+    }
+
+    /// The matched brackets of the code in `lines`, from their highlighting
+    fn bracket_pairs(&self, styled: &[StyledLine], lines: Range<usize>) -> Vec<PairRanges> {
+        let mut pairs = Vec::new();
+        let mut open: Vec<(usize, char)> = Vec::new();
+        for (l, styled_line) in styled.iter().enumerate().take(lines.end).skip(lines.start) {
+            let start = self.rope.line_to_byte(l);
+            // the highlighting may be late on the last edit
+            let brackets = styled_line
+                .brackets
+                .iter()
+                .filter(|b| self.rope.get_byte(start + b.offset) == Some(b.char as u8));
+            for b in brackets {
+                if b.is_open() {
+                    open.push((start + b.offset, b.char));
+                } else if let Some(o) = open.iter().rposition(|o| BRACKETS.contains(&(o.1, b.char))) {
+                    pairs.push((open[o].0..open[o].0 + 1, start + b.offset..start + b.offset + 1));
+                    open.truncate(o);
+                }
+            }
+        }
+        pairs
+    }
+
+    /// The matched tags of `lines`, from the tag names found by the highlighter, so only in the markup languages.
+    /// `html` tells if the HTML void elements have no closing tag.
+    fn tag_pairs(&self, styled: &[StyledLine], lines: Range<usize>, html: bool) -> Vec<PairRanges> {
+        let mut pairs = Vec::new();
+        let mut open: Vec<(String, Range<usize>)> = Vec::new();
+        for tag in self.tags(styled, lines, html).into_iter().filter(|t| !t.empty) {
+            let end = match self.tag_end(tag.name.end) {
+                Some(end) => end.index,
+                None => continue,
+            };
+            let name = self.tag_name(&tag);
+            if tag.closing {
+                if let Some(o) = open.iter().rposition(|t| t.0 == name) {
+                    pairs.push((open[o].1.clone(), tag.name.start.index - 2..end));
+                    open.truncate(o);
+                }
+            } else {
+                open.push((name, tag.name.start.index - 1..end));
+            }
+        }
+        pairs
+    }
+
+    /// The quotes of the string around `r`, from the highlighting of its line
+    fn quotes_around(&self, styled: &[StyledLine], r: &Range<usize>) -> Option<PairRanges> {
+        let line = self.rope.byte_to_line(r.start);
+        let start = self.rope.line_to_byte(line);
+        let text = self.rope.line(line).to_string();
+        let (r_start, r_end) = (r.start - start, r.end - start);
+        styled
+            .get(line)?
+            .literals()
+            .iter()
+            .filter(|l| l.start < r_start && r_end < l.end && l.end <= text.len())
+            .find_map(|l| {
+                let literal = text.get(l.clone())?;
+                // a string, maybe with a prefix like `r` or `f`, not a comment
+                let open = literal.find(|c| QUOTES.contains(&c))?;
+                let quote = literal[open..].chars().next()?;
+                if !literal[..open].chars().all(char::is_alphanumeric)
+                    || literal.len() < open + 2
+                    || !literal.ends_with(quote)
+                {
+                    return None;
+                }
+                let (open, close) = (start + l.start + open, start + l.end - 1);
+                (open < r.start && r.end <= close).then_some((open..open + 1, close..close + 1))
+            })
+    }
+
+    /// Replace the innermost brackets, quotes or tag around each caret by `surrounding`, or remove them
+    /// if it is `None`. Returns false if no caret is surrounded.
+    /// The brackets and the quotes are the ones of the code, from the highlighting `styled`,
+    /// the tags are the ones of the markup languages, `html` telling if the HTML void elements have no closing tag.
+    pub fn replace_surrounding(
+        &mut self,
+        surrounding: Option<&Surrounding>,
+        styled: &[StyledLine],
+        html: bool,
+    ) -> bool {
+        let first = self.carets.iter().map(|c| c.start_line(self).index).min().unwrap_or(0);
+        let last = self.carets.iter().map(|c| c.end_line(self).index).max().unwrap_or(0);
+        let lines = first.saturating_sub(SEARCH_LINES)..(last + SEARCH_LINES + 1).min(self.len_lines());
+        let mut pairs = self.bracket_pairs(styled, lines.clone());
+        pairs.extend(self.tag_pairs(styled, lines, html));
+        let found: Vec<PairRanges> = self
+            .carets
+            .iter()
+            .filter_map(|c| {
+                let r = c.start().index..c.end().index;
+                pairs
+                    .iter()
+                    .filter(|p| p.0.end <= r.start && p.1.start >= r.end)
+                    .cloned()
+                    .chain(self.quotes_around(styled, &r))
+                    .max_by_key(|p| p.0.start)
+            })
+            .collect();
+        if found.is_empty() {
+            return false;
+        }
+        let (open, close) = surrounding.map_or(("", ""), |s| (s.open.as_str(), s.close.as_str()));
+        // the pairs of different carets can be nested, so all the edits are done from the end of the text
+        let mut edits: Vec<(Range<usize>, &str)> =
+            found.into_iter().flat_map(|(o, c)| [(o, open), (c, close)]).collect();
+        edits.sort_by_key(|(r, _)| std::cmp::Reverse(r.start));
+        edits.dedup();
+        for (r, text) in edits {
+            self.edit(&(Absolute::from(r.start)..Absolute::from(r.end)), text);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::widgets::text_buffer::syntax::highlight_all;
+
+    fn replace(b: &mut Buffer, surrounding: Option<&str>, syntax: &str) -> bool {
+        let styled = highlight_all(&b.rope, syntax);
+        let surrounding = surrounding.map(|s| Surrounding::parse(s).unwrap());
+        b.replace_surrounding(surrounding.as_ref(), &styled, syntax == "HTML")
+    }
+
+    #[test]
+    fn code_surroundings() {
+        // the comparison operators and the brackets of the strings are not pairs
+        let mut b = Buffer::with_caret("let v = (a < b, c > \")\");", 12..12);
+        assert!(replace(&mut b, Some("["), "Rust"));
+        assert_eq!(b.rope.to_string(), "let v = [a < b, c > \")\"];");
+
+        let mut b = Buffer::with_caret("f(\"a)b\", x);", 4..4);
+        assert!(replace(&mut b, Some("'"), "Python"));
+        assert_eq!(b.rope.to_string(), "f('a)b', x);");
+        assert!(replace(&mut b, None, "Python"));
+        assert_eq!(b.rope.to_string(), "f(a)b, x);");
+        assert!(!replace(&mut Buffer::with_caret("a # (b)", 5..5), None, "Python"));
+    }
+
+    #[test]
+    fn nested_under_two_carets() {
+        let two_carets = |text: &str| {
+            let mut b = Buffer::with_caret(text, 2..2);
+            let c = b.carets[0].duplicate_to(Absolute::from(5), Absolute::from(5), &b);
+            b.carets.push(c);
+            b
+        };
+        let mut b = two_carets("((a) b)");
+        assert!(replace(&mut b, None, "Rust"));
+        assert_eq!(b.rope.to_string(), "a b");
+        let mut b = two_carets("((a) b)");
+        assert!(replace(&mut b, Some("<b>"), "Rust"));
+        assert_eq!(b.rope.to_string(), "<b><b>a</b> b</b>");
+    }
+
+    #[test]
+    fn tags() {
+        let mut b = Buffer::with_caret("<p>a <i>d</i></p>", 9..9);
+        assert!(replace(&mut b, Some("<b>"), "HTML"));
+        assert_eq!(b.rope.to_string(), "<p>a <b>d</b></p>");
+        assert!(replace(&mut b, None, "HTML"));
+        assert_eq!(b.rope.to_string(), "<p>a d</p>");
+
+        let mut b = Buffer::with_caret("a b", 0..1);
+        b.surround(&Surrounding::parse("<a href=\"x\">").unwrap());
+        assert_eq!(b.rope.to_string(), "<a href=\"x\">a</a> b");
+    }
+}
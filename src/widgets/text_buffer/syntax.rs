@@ -6,16 +6,22 @@ use std::{
 };
 use syntect::{
     highlighting::{HighlightState, Highlighter, RangedHighlightIterator, Style},
-    parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet},
+    parsing::{MetadataItems, ParseState, Scope, ScopeStack, ScopeStackOp, SyntaxReference, SyntaxSet},
 };
 
 use crate::theme::THEME;
 
 pub static SYNTAXSET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
 
+/// The scopes of string and comment, where the text is not code
+static LITERAL_SCOPES: Lazy<[Scope; 2]> = Lazy::new(|| [Scope::new("string").unwrap(), Scope::new("comment").unwrap()]);
+/// The scope of the names of the markup tags
+static TAG_SCOPES: Lazy<[Scope; 1]> = Lazy::new(|| [Scope::new("entity.name.tag").unwrap()]);
+
 #[derive(Debug)]
 pub struct StateCache {
-    states: Vec<(ParseState, HighlightState)>,
+    /// The parser and highlighter states, and the bracket depth, at the start of every 16 lines
+    states: Vec<(ParseState, HighlightState, usize)>,
     highlighter: Highlighter<'static>,
 }
 #[derive(Debug)]
@@ -30,15 +36,117 @@ impl SpanStyle {
     }
 }
 
+/// A bracket of the code, outside of strings and comments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bracket {
+    /// The byte offset in the line
+    pub offset: usize,
+    pub char: char,
+    /// The nesting depth, the same for an opening bracket and the one closing it
+    pub depth: usize,
+}
+
+impl Bracket {
+    pub fn is_open(&self) -> bool {
+        matches!(self.char, '(' | '[' | '{')
+    }
+
+    fn closes(&self, open: &Bracket) -> bool {
+        matches!((open.char, self.char), ('(', ')') | ('[', ']') | ('{', '}'))
+    }
+}
+
 #[derive(Debug)]
 pub struct StyledLine {
     styles: Vec<SpanStyle>,
+    /// The byte ranges of the line in a string or a comment
+    literals: Vec<Range<usize>>,
+    pub brackets: Vec<Bracket>,
+    /// The byte ranges of the line in a tag name scope
+    pub tag_names: Vec<Range<usize>>,
 }
 
 impl StyledLine {
-    pub fn new(styles: Vec<SpanStyle>) -> Self {
-        Self { styles }
+    pub fn new(
+        styles: Vec<SpanStyle>,
+        literals: Vec<Range<usize>>,
+        brackets: Vec<Bracket>,
+        tag_names: Vec<Range<usize>>,
+    ) -> Self {
+        Self {
+            styles,
+            literals,
+            brackets,
+            tag_names,
+        }
+    }
+
+    /// Check if the byte `offset` of the line is inside a string or a comment
+    pub fn in_literal(&self, offset: usize) -> bool {
+        self.literals.iter().any(|r| r.start < offset && offset < r.end)
+    }
+
+    /// The byte ranges of the strings and comments of the line
+    pub fn literals(&self) -> &[Range<usize>] {
+        &self.literals
+    }
+}
+
+/// The brackets of `line` not in the `literals` ranges, `depth` being the nesting depth at the line start
+fn code_brackets(line: &str, literals: &[Range<usize>], depth: &mut usize) -> Vec<Bracket> {
+    line.char_indices()
+        .filter(|(i, c)| matches!(c, '(' | ')' | '[' | ']' | '{' | '}') && !literals.iter().any(|r| r.contains(i)))
+        .map(|(offset, c)| {
+            let mut bracket = Bracket {
+                offset,
+                char: c,
+                depth: *depth,
+            };
+            if bracket.is_open() {
+                *depth += 1;
+            } else {
+                *depth = depth.saturating_sub(1);
+                bracket.depth = *depth;
+            }
+            bracket
+        })
+        .collect()
+}
+
+/// The ranges of a line of `len` bytes in one of the `scopes`, `stack` being the scopes at the line start
+fn scope_ranges(
+    mut stack: ScopeStack,
+    ops: &[(usize, ScopeStackOp)],
+    len: usize,
+    scopes: &[Scope],
+) -> Vec<Range<usize>> {
+    let in_scopes = |stack: &ScopeStack| {
+        stack
+            .as_slice()
+            .iter()
+            .any(|s| scopes.iter().any(|l| l.is_prefix_of(*s)))
+    };
+    let mut ranges = Vec::new();
+    let mut start = if in_scopes(&stack) { Some(0) } else { None };
+    for (i, op) in ops {
+        stack.apply(op);
+        match (start, in_scopes(&stack)) {
+            // a scope popped and pushed again at the same offset, like the quotes of some strings, continues the range
+            (None, true) if ranges.last().is_some_and(|r: &Range<usize>| r.end == *i) => {
+                start = ranges.pop().map(|r| r.start)
+            }
+            (None, true) => start = Some(*i),
+            (Some(s), false) => {
+                ranges.push(s..*i);
+                start = None;
+            }
+            _ => (),
+        }
     }
+    if let Some(s) = start {
+        ranges.push(s..len);
+    }
+    ranges
 }
 
 impl Deref for StyledLine {
@@ -58,6 +166,57 @@ impl StyledLinesCache {
     pub fn new() -> Self { Self { lines: Arc::new(Mutex::new(Vec::new())) } }
 }
 
+/// The metadata items of `syntax`, like its comment tokens and indentation patterns, the best matching first
+pub fn metadata_items(syntax: &SyntaxReference) -> Vec<&'static MetadataItems> {
+    SYNTAXSET
+        .metadata()
+        .metadata_for_scope(&[syntax.scope])
+        .items
+        .into_iter()
+        .map(|(_, set)| &set.items)
+        .collect()
+}
+
+/// Highlight all the lines of `rope` at once
+#[cfg(test)]
+pub fn highlight_all(rope: &Rope, syntax_name: &str) -> Vec<StyledLine> {
+    let cache = StyledLinesCache::new();
+    let syntax = SYNTAXSET.find_syntax_by_name(syntax_name).unwrap();
+    StateCache::new().update_range(cache.clone(), syntax, rope, 0, rope.len_lines());
+    let lines = std::mem::take(&mut *cache.lines.lock().unwrap());
+    lines
+}
+
+/// The bracket just after, or else just before, the byte `offset` of `line`, and the bracket matching it,
+/// searched in the lines of `search_range`. The brackets are given as line index and bracket.
+pub fn matching_bracket(
+    lines: &[StyledLine],
+    line: usize,
+    offset: usize,
+    search_range: Range<usize>,
+) -> Option<((usize, Bracket), (usize, Bracket))> {
+    let brackets = &lines.get(line)?.brackets;
+    let bracket = brackets
+        .iter()
+        .find(|b| b.offset == offset)
+        .or_else(|| brackets.iter().find(|b| b.offset + 1 == offset))?;
+    let same_depth = |b: &&Bracket| b.depth == bracket.depth;
+    let found = if bracket.is_open() {
+        (line..search_range.end.min(lines.len()))
+            .flat_map(|l| lines[l].brackets.iter().map(move |b| (l, b)))
+            .filter(|(l, b)| *l > line || b.offset > bracket.offset)
+            .find(|(_, b)| same_depth(b))
+            .filter(|(_, b)| b.closes(bracket))
+    } else {
+        (search_range.start..line + 1)
+            .rev()
+            .flat_map(|l| lines[l].brackets.iter().rev().map(move |b| (l, b)))
+            .filter(|(l, b)| *l < line || b.offset < bracket.offset)
+            .find(|(_, b)| same_depth(b))
+            .filter(|(_, b)| bracket.closes(b))
+    };
+    found.map(|(l, b)| ((line, *bracket), (l, *b)))
+}
 
 impl StateCache {
     pub fn new() -> Self {
@@ -85,23 +244,30 @@ impl StateCache {
             (
                 ParseState::new(syntax),
                 HighlightState::new(&self.highlighter, ScopeStack::new()),
+                0,
             )
         });
 
         for i in start << 4..(end << 4).min(rope.len_lines()) {
             let h = if let Some(str) = rope.line(i).as_str() {
                 let ops = states.0.parse_line(&str, &SYNTAXSET);
+                let literals = scope_ranges(states.1.path.clone(), &ops, str.len(), &*LITERAL_SCOPES);
+                let tag_names = scope_ranges(states.1.path.clone(), &ops, str.len(), &*TAG_SCOPES);
+                let brackets = code_brackets(str, &literals, &mut states.2);
                 let h: Vec<_> = RangedHighlightIterator::new(&mut states.1, &ops, &str, &self.highlighter)
                     .map(|h| SpanStyle::new(h.0, h.2))
                     .collect();
-                StyledLine::new(h)
+                StyledLine::new(h, literals, brackets, tag_names)
             } else {
                 let str = rope.line(i).to_string();
                 let ops = states.0.parse_line(&str, &SYNTAXSET);
+                let literals = scope_ranges(states.1.path.clone(), &ops, str.len(), &*LITERAL_SCOPES);
+                let tag_names = scope_ranges(states.1.path.clone(), &ops, str.len(), &*TAG_SCOPES);
+                let brackets = code_brackets(&str, &literals, &mut states.2);
                 let h: Vec<_> = RangedHighlightIterator::new(&mut states.1, &ops, &str, &self.highlighter)
                     .map(|h| SpanStyle::new(h.0, h.2))
                     .collect();
-                StyledLine::new(h)
+                StyledLine::new(h, literals, brackets, tag_names)
             };
             if i & 0xF == 0xF {
                 self.states.push(states.clone());
@@ -115,3 +281,27 @@ impl StateCache {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brackets_matching() {
+        let mut depth = 0;
+        let lines: Vec<StyledLine> = ["f(a[0], \")\") {", "}"]
+            .iter()
+            .map(|l| {
+                let literals: Vec<_> = l.find('"').map(|_| 8..11).into_iter().collect();
+                let brackets = code_brackets(l, &literals, &mut depth);
+                StyledLine::new(Vec::new(), literals, brackets, Vec::new())
+            })
+            .collect();
+        assert_eq!(lines[0].brackets.len(), 5);
+        let pair = matching_bracket(&lines, 0, 1, 0..2).unwrap();
+        assert_eq!((pair.1 .0, pair.1 .1.offset), (0, 11));
+        let pair = matching_bracket(&lines, 1, 1, 0..2).unwrap();
+        assert_eq!((pair.1 .0, pair.1 .1.offset), (0, 13));
+        assert!(matching_bracket(&lines, 1, 1, 1..2).is_none());
+    }
+}
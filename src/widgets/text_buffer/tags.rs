@@ -0,0 +1,231 @@
+use std::ops::Range;
+
+use super::buffer::Buffer;
+use super::position::{Absolute, Line, Position};
+use super::syntax::StyledLine;
+
+/// The HTML elements without closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// A tag whose name was found by the highlighter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Tag {
+    pub name: Range<Absolute>,
+    pub closing: bool,
+    /// Self-closing, or an HTML void element
+    pub empty: bool,
+}
+
+impl Buffer {
+    /// The tags of `lines`, from the tag name scopes of their highlighting
+    pub(super) fn tags(&self, styled: &[StyledLine], lines: Range<usize>, html: bool) -> Vec<Tag> {
+        let mut tags = Vec::new();
+        let end = lines.end.min(self.len_lines());
+        for (l, styled_line) in styled.iter().enumerate().take(end).skip(lines.start) {
+            let start = Line::from(l).start(self);
+            for r in styled_line.tag_names.iter() {
+                let name = start + r.start..start + r.end;
+                // the highlighting may be late on the last edit
+                if name.end > Line::from(l).end(self) {
+                    continue;
+                }
+                let closing = match (self.byte_before(name.start, 1), self.byte_before(name.start, 2)) {
+                    (Some(b'/'), Some(b'<')) => true,
+                    (Some(b'<'), _) => false,
+                    _ => continue,
+                };
+                let empty = !closing
+                    && (self
+                        .tag_end(name.end)
+                        .is_some_and(|end| self.byte_before(end, 2) == Some(b'/'))
+                        || html
+                            && VOID_ELEMENTS.contains(&self.slice(name.clone()).to_string().to_lowercase().as_str()));
+                tags.push(Tag { name, closing, empty });
+            }
+        }
+        tags
+    }
+
+    /// The index after the `>` ending the tag whose name ends at `index`, if it is on the same line
+    pub(super) fn tag_end(&self, index: Absolute) -> Option<Absolute> {
+        let line_end = index.line(self).end(self);
+        self.slice(index..line_end)
+            .bytes()
+            .take_while(|b| *b != b'<')
+            .position(|b| b == b'>')
+            .map(|i| index + i + 1)
+    }
+
+    fn byte_before(&self, index: Absolute, n: usize) -> Option<u8> {
+        index.index.checked_sub(n).map(|i| self.rope.byte(i))
+    }
+
+    pub(super) fn tag_name(&self, tag: &Tag) -> String {
+        self.slice(tag.name.clone()).to_string()
+    }
+
+    /// After a `>` typed at the carets, insert the closing tag of the opening tag it ends
+    pub fn close_tags(&mut self, styled: &[StyledLine], html: bool) {
+        for i in self.carets_by_index_desc() {
+            let index = self.carets[i].index;
+            if !self.carets[i].selection_is_empty() || self.byte_before(index, 1) != Some(b'>') {
+                continue;
+            }
+            let line = index.line(self).index;
+            let tag = self
+                .tags(styled, line..line + 1, html)
+                .into_iter()
+                .rfind(|t| t.name.end < index)
+                .filter(|t| !t.closing && !t.empty && self.tag_end(t.name.end) == Some(index));
+            if let Some(tag) = tag {
+                let close = format!("</{}>", self.tag_name(&tag));
+                self.edit(&(index..index), &close);
+            }
+        }
+    }
+
+    /// After a `/` typed just after a `<` at the carets, complete the closing tag of the nearest unclosed tag
+    pub fn complete_closing_tags(&mut self, styled: &[StyledLine], html: bool) {
+        for i in self.carets_by_index_desc() {
+            let index = self.carets[i].index;
+            if !self.carets[i].selection_is_empty() || self.byte_before(index, 2) != Some(b'<') {
+                continue;
+            }
+            let line = index.line(self).index;
+            let mut open: Vec<Tag> = Vec::new();
+            for tag in self.tags(styled, 0..line + 1, html) {
+                if tag.name.start >= index {
+                    break;
+                }
+                if tag.closing {
+                    let name = self.tag_name(&tag);
+                    if let Some(o) = open.iter().rposition(|t| self.tag_name(t) == name) {
+                        open.truncate(o);
+                    }
+                } else if !tag.empty {
+                    open.push(tag);
+                }
+            }
+            if let Some(tag) = open.last() {
+                let name = self.tag_name(tag);
+                let (text, caret) = if self.rope.get_byte(index.index) == Some(b'>') {
+                    (name.clone(), index + name.len() + 1)
+                } else {
+                    (format!("{}>", name), index + name.len() + 1)
+                };
+                self.edit(&(index..index), &text);
+                self.set_caret_index(i, caret, true, true);
+            }
+        }
+        self.carets.merge();
+    }
+
+    /// Select the name of the tag at the main caret and the one of its partner with two carets,
+    /// so they are renamed together. Returns false if the caret is not on a paired tag name.
+    pub fn select_tag_pair(&mut self, styled: &[StyledLine], html: bool) -> bool {
+        let index = self.main_caret().index;
+        let tags = self.tags(styled, 0..self.len_lines(), html);
+        let current = match tags.iter().position(|t| t.name.start <= index && index <= t.name.end) {
+            Some(c) if !tags[c].empty => c,
+            _ => return false,
+        };
+        let name = self.tag_name(&tags[current]);
+        // the tags of the same name at each nesting depth, to find the partner of the current one
+        let mut depth = 0usize;
+        let mut partner = None;
+        if tags[current].closing {
+            for t in tags[..current]
+                .iter()
+                .rev()
+                .filter(|t| !t.empty && self.tag_name(t) == name)
+            {
+                if t.closing {
+                    depth += 1;
+                } else if depth == 0 {
+                    partner = Some(t);
+                    break;
+                } else {
+                    depth -= 1;
+                }
+            }
+        } else {
+            for t in tags[current + 1..]
+                .iter()
+                .filter(|t| !t.empty && self.tag_name(t) == name)
+            {
+                if !t.closing {
+                    depth += 1;
+                } else if depth == 0 {
+                    partner = Some(t);
+                    break;
+                } else {
+                    depth -= 1;
+                }
+            }
+        }
+        let partner = match partner {
+            Some(p) => p.name.clone(),
+            None => return false,
+        };
+        let own = tags[current].name.clone();
+        self.cancel_mutli_carets();
+        self.set_caret_index(0, own.start, true, true);
+        self.set_caret_index(0, own.end, false, true);
+        let mut carets = std::mem::take(&mut self.carets);
+        carets.add_selections(Some(partner), self);
+        self.carets = carets;
+        true
+    }
+
+    /// The caret indices, the last caret in the buffer first, so edits don't move the next ones
+    fn carets_by_index_desc(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.carets.len()).collect();
+        indices.sort_by_key(|i| std::cmp::Reverse(self.carets[*i].index));
+        indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::widgets::text_buffer::syntax::highlight_all;
+
+    fn highlight(b: &Buffer) -> Vec<StyledLine> {
+        highlight_all(&b.rope, "HTML")
+    }
+
+    #[test]
+    fn close_and_complete() {
+        let mut b = Buffer::with_caret("<div class=\"a\">", 15..15);
+        b.close_tags(&highlight(&b), true);
+        assert_eq!(b.rope.to_string(), "<div class=\"a\"></div>");
+        assert_eq!(b.main_caret().index, Absolute::from(15));
+
+        let mut b = Buffer::with_caret("<br>", 4..4);
+        b.close_tags(&highlight(&b), true);
+        assert_eq!(b.rope.to_string(), "<br>");
+
+        let mut b = Buffer::with_caret("<ul>\n<li><b>x</b>\n</", 20..20);
+        b.complete_closing_tags(&highlight(&b), true);
+        assert_eq!(b.rope.to_string(), "<ul>\n<li><b>x</b>\n</li>");
+        assert_eq!(b.main_caret().index, Absolute::from(23));
+    }
+
+    #[test]
+    fn rename_pair() {
+        let mut b = Buffer::with_caret("<a><a>x</a></a>", 13..13);
+        assert!(b.select_tag_pair(&highlight(&b), true));
+        let ranges: Vec<_> = b.carets.iter().map(|c| c.range()).collect();
+        assert_eq!(
+            ranges,
+            vec![
+                Absolute::from(1)..Absolute::from(2),
+                Absolute::from(13)..Absolute::from(14)
+            ]
+        );
+        b.insert("span", false);
+        assert_eq!(b.rope.to_string(), "<span><a>x</a></span>");
+    }
+}
@@ -0,0 +1,356 @@
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use anyhow::{anyhow, bail, Context};
+use base64::{engine::general_purpose, Engine as _};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use super::buffer::Buffer;
+use super::file::LineFeed;
+
+/// The transformations of `Buffer::transform_selections`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    Base64Encode,
+    Base64Decode,
+    UrlEncode,
+    UrlDecode,
+    HtmlEscape,
+    HtmlUnescape,
+    JsonEscape,
+    JsonUnescape,
+    HexDump,
+    Sha256,
+    Md5,
+    /// Compute an arithmetic expression
+    Evaluate,
+}
+
+impl Transform {
+    pub fn apply(self, text: &str, line_feed: LineFeed) -> anyhow::Result<String> {
+        Ok(match self {
+            Transform::Base64Encode => general_purpose::STANDARD.encode(text),
+            Transform::Base64Decode => {
+                let text: String = text.split_whitespace().collect();
+                let bytes = general_purpose::STANDARD
+                    .decode(&text)
+                    .or_else(|_| general_purpose::URL_SAFE.decode(&text))
+                    .context("Invalid base64")?;
+                String::from_utf8(bytes).context("The decoded base64 is not UTF-8 text")?
+            }
+            Transform::UrlEncode => url_encode(text),
+            Transform::UrlDecode => url_decode(text)?,
+            Transform::HtmlEscape => html_escape(text),
+            Transform::HtmlUnescape => html_unescape(text)?,
+            Transform::JsonEscape => {
+                let quoted = serde_json::to_string(text)?;
+                quoted[1..quoted.len() - 1].to_owned()
+            }
+            Transform::JsonUnescape => {
+                let quoted = if text.len() > 1 && text.starts_with('"') && text.ends_with('"') {
+                    text.to_owned()
+                } else {
+                    format!("\"{}\"", text)
+                };
+                serde_json::from_str::<String>(&quoted).context("Invalid JSON string")?
+            }
+            Transform::HexDump => hex_dump(text.as_bytes(), line_feed),
+            Transform::Sha256 => to_hex(&Sha256::digest(text)),
+            Transform::Md5 => to_hex(&Md5::digest(text)),
+            Transform::Evaluate => {
+                let value = Evaluator::new(text).evaluate()?;
+                if value.fract() == 0. && value.abs() < 1e15 {
+                    format!("{}", value as i64)
+                } else {
+                    format!("{}", value)
+                }
+            }
+        })
+    }
+}
+
+fn url_encode(text: &str) -> String {
+    let mut result = String::new();
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            result.push(b as char);
+        } else {
+            let _ = write!(result, "%{:02X}", b);
+        }
+    }
+    result
+}
+
+fn url_decode(text: &str) -> anyhow::Result<String> {
+    let mut bytes = Vec::new();
+    let mut it = text.bytes();
+    while let Some(b) = it.next() {
+        if b == b'%' {
+            let hex: Vec<u8> = it.by_ref().take(2).collect();
+            let hex = std::str::from_utf8(&hex).ok().filter(|h| h.len() == 2);
+            let byte = hex
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .ok_or_else(|| anyhow!("Invalid escape sequence in URL"))?;
+            bytes.push(byte);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).context("The decoded URL is not UTF-8 text")
+}
+
+fn html_escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Replace the named entities of `html_escape`, `&nbsp;` and `&apos;`, and the numeric ones.
+/// Other `&` are left as is.
+fn html_unescape(text: &str) -> anyhow::Result<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let c = match entity {
+            Some("amp") => Some('&'),
+            Some("lt") => Some('<'),
+            Some("gt") => Some('>'),
+            Some("quot") => Some('"'),
+            Some("apos") => Some('\''),
+            Some("nbsp") => Some('\u{a0}'),
+            Some(e) if e.starts_with('#') => {
+                let code = match e[1..].strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => e[1..].parse(),
+                };
+                let c = code.ok().and_then(char::from_u32);
+                Some(c.ok_or_else(|| anyhow!("Invalid character reference &{};", e))?)
+            }
+            _ => None,
+        };
+        match (c, entity) {
+            (Some(c), Some(e)) => {
+                result.push(c);
+                rest = &rest[e.len() + 2..];
+            }
+            _ => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Offsets, hexadecimal bytes and printable chars, 16 bytes a line
+fn hex_dump(bytes: &[u8], line_feed: LineFeed) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut hex: String = chunk.iter().map(|b| format!("{:02x} ", b)).collect();
+            hex.insert(24.min(hex.len()), ' ');
+            let ascii: String = chunk
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_graphic() || *b == b' ' {
+                        *b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}  {:<49} |{}|", i * 16, hex, ascii)
+        })
+        .collect::<Vec<String>>()
+        .join(line_feed.to_str())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A recursive descent parser of the arithmetic expressions, with `+ - * / % ^` and parentheses
+struct Evaluator<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(text: &'a str) -> Self {
+        Evaluator {
+            chars: text.chars().peekable(),
+        }
+    }
+
+    fn evaluate(mut self) -> anyhow::Result<f64> {
+        let value = self.sum()?;
+        match self.next_token() {
+            None => Ok(value),
+            Some(c) => bail!("Unexpected '{}' in the expression", c),
+        }
+    }
+
+    fn next_token(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    fn sum(&mut self) -> anyhow::Result<f64> {
+        let mut value = self.product()?;
+        while let Some(op @ ('+' | '-')) = self.next_token() {
+            self.chars.next();
+            let rhs = self.product()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> anyhow::Result<f64> {
+        let mut value = self.power()?;
+        while let Some(op @ ('*' | '/' | '%')) = self.next_token() {
+            self.chars.next();
+            let rhs = self.power()?;
+            if op != '*' && rhs == 0. {
+                bail!("Division by zero");
+            }
+            value = match op {
+                '*' => value * rhs,
+                '/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    /// The power is right associative and binds tighter than the unary minus on its left
+    fn power(&mut self) -> anyhow::Result<f64> {
+        if self.next_token() == Some('-') {
+            self.chars.next();
+            return Ok(-self.power()?);
+        }
+        let base = self.atom()?;
+        if self.next_token() == Some('^') {
+            self.chars.next();
+            return Ok(base.powf(self.power()?));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> anyhow::Result<f64> {
+        match self.next_token() {
+            Some('(') => {
+                self.chars.next();
+                let value = self.sum()?;
+                if self.next_token() != Some(')') {
+                    bail!("Missing ')' in the expression");
+                }
+                self.chars.next();
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.' || *c == '_') {
+                    if c != '_' {
+                        number.push(c);
+                    }
+                }
+                number.parse().map_err(|_| anyhow!("Invalid number {}", number))
+            }
+            Some(c) => bail!("Unexpected '{}' in the expression", c),
+            None => bail!("Incomplete expression"),
+        }
+    }
+}
+
+impl Buffer {
+    /// Replace each selection by its transformation, which stays selected.
+    /// Nothing is changed if a selection can't be transformed.
+    pub fn transform_selections(&mut self, transform: Transform, line_feed: LineFeed) -> anyhow::Result<()> {
+        let mut carets: Vec<usize> = (0..self.carets.len())
+            .filter(|i| !self.carets[*i].selection_is_empty())
+            .collect();
+        if carets.is_empty() {
+            bail!("Nothing selected");
+        }
+        carets.sort_by_key(|i| std::cmp::Reverse(self.carets[*i].start()));
+        let texts = carets
+            .iter()
+            .map(|i| transform.apply(&self.slice(self.carets[*i].range()).to_string(), line_feed))
+            .collect::<anyhow::Result<Vec<String>>>()?;
+        for (i, text) in carets.into_iter().zip(texts) {
+            let r = self.carets[i].range();
+            self.edit(&r, &text);
+            self.set_caret_index(i, r.start, true, true);
+            self.set_caret_index(i, r.start + text.len(), false, true);
+        }
+        self.carets.merge();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(t: Transform, text: &str) -> String {
+        t.apply(text, LineFeed::Lf).unwrap()
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!(apply(Transform::Base64Encode, "héllo"), "aMOpbGxv");
+        assert_eq!(apply(Transform::Base64Decode, "aMOpbGxv"), "héllo");
+        assert!(Transform::Base64Decode.apply("a!", LineFeed::Lf).is_err());
+        assert_eq!(apply(Transform::UrlEncode, "a b&é"), "a%20b%26%C3%A9");
+        assert_eq!(apply(Transform::UrlDecode, "a%20b%26%C3%A9"), "a b&é");
+        assert!(Transform::UrlDecode.apply("%4", LineFeed::Lf).is_err());
+        assert_eq!(
+            apply(Transform::HtmlEscape, "<a href=\"x\">&</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+        assert_eq!(
+            apply(Transform::HtmlUnescape, "&lt;&#233;&#x41;&foo; & x&gt;"),
+            "<éA&foo; & x>"
+        );
+        assert_eq!(apply(Transform::JsonEscape, "a\"b\n"), "a\\\"b\\n");
+        assert_eq!(apply(Transform::JsonUnescape, "a\\\"b\\n"), "a\"b\n");
+        assert_eq!(
+            apply(Transform::HexDump, "Hello"),
+            "00000000  48 65 6c 6c 6f                                    |Hello|"
+        );
+    }
+
+    #[test]
+    fn digests() {
+        assert_eq!(
+            apply(Transform::Sha256, "abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(apply(Transform::Md5, ""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(apply(Transform::Md5, "abc"), "900150983cd24fb0d6963f7d28e17f72");
+    }
+
+    #[test]
+    fn evaluate() {
+        assert_eq!(apply(Transform::Evaluate, "1 + 2 * (3 - 1)"), "5");
+        assert_eq!(apply(Transform::Evaluate, "-2^2 + 10 % 4"), "-2");
+        assert_eq!(apply(Transform::Evaluate, "1 / 4"), "0.25");
+        assert!(Transform::Evaluate.apply("1 / 0", LineFeed::Lf).is_err());
+        assert!(Transform::Evaluate.apply("2 +", LineFeed::Lf).is_err());
+    }
+}
@@ -0,0 +1,859 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use druid::im::Vector;
+use ropey::Rope;
+use sha2::{Digest, Sha256};
+
+use super::buffer::Buffer;
+use super::caret::Carets;
+use super::position::Absolute;
+
+/// Memory used by the undo history before the oldest entries get dropped
+pub const DEFAULT_UNDO_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// A pause longer than this starts a new undo step
+const GROUP_TIMEOUT: Duration = Duration::from_secs(1);
+
+const HISTORY_FILE_MAGIC: &[u8; 8] = b"NPUNDO02";
+
+/// A history using more memory than this is not kept after closing the file
+const HISTORY_FILE_LIMIT: usize = 16 * 1024 * 1024;
+
+/// The size of all the kept histories, beyond which the least recently saved ones are deleted
+const HISTORY_CACHE_LIMIT: u64 = 256 * 1024 * 1024;
+
+/// The kept histories not saved for this long are deleted
+const HISTORY_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// What kind of action produced a transaction.
+/// Consecutive transactions of the same kind, other than `Other`, are grouped in one undo step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Insert,
+    Backspace,
+    Delete,
+    Other,
+}
+
+impl EditKind {
+    fn from_u8(kind: u8) -> Self {
+        match kind {
+            0 => EditKind::Insert,
+            1 => EditKind::Backspace,
+            2 => EditKind::Delete,
+            _ => EditKind::Other,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            EditKind::Insert => 0,
+            EditKind::Backspace => 1,
+            EditKind::Delete => 2,
+            EditKind::Other => 3,
+        }
+    }
+}
+
+/// A single replacement in the buffer, with enough information to revert it
+#[derive(Debug, Clone)]
+pub struct EditOp {
+    pub start: Absolute,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl EditOp {
+    /// The range removed by this edit, before it was applied
+    pub fn removed_range(&self) -> Range<Absolute> {
+        self.start..self.start + self.removed.len()
+    }
+
+    /// The range of the inserted text, after the edit was applied
+    pub fn inserted_range(&self) -> Range<Absolute> {
+        self.start..self.start + self.inserted.len()
+    }
+}
+
+type CaretPositions = Vec<(Absolute, Absolute, bool)>;
+
+/// The edits made by one user action and the carets around it
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    ops: Vec<EditOp>,
+    carets_before: CaretPositions,
+    carets_after: CaretPositions,
+    kind: EditKind,
+    time: SystemTime,
+}
+
+impl Transaction {
+    pub fn new(kind: EditKind, ops: Vec<EditOp>, carets_before: &Carets, carets_after: &Carets) -> Self {
+        Self {
+            ops,
+            carets_before: carets_before.positions(),
+            carets_after: carets_after.positions(),
+            kind,
+            time: SystemTime::now(),
+        }
+    }
+
+    /// Check if `next` continue this transaction: same kind of edit, typed without pause at the last caret position
+    fn can_group_with(&self, next: &Transaction) -> bool {
+        self.kind != EditKind::Other
+            && self.kind == next.kind
+            && next
+                .time
+                .duration_since(self.time)
+                .is_ok_and(|pause| pause < GROUP_TIMEOUT)
+            && self.carets_after.len() == next.carets_before.len()
+            && self
+                .carets_after
+                .iter()
+                .zip(next.carets_before.iter())
+                .all(|(a, b)| a.0 == b.0 && a.1 == b.1)
+    }
+
+    fn undo(&self, buffer: &mut Buffer) {
+        for op in self.ops.iter().rev() {
+            buffer.edit(&op.inserted_range(), &op.removed);
+        }
+        buffer.take_journal();
+        buffer.carets = Carets::from_positions(&self.carets_before, buffer);
+    }
+
+    fn redo(&self, buffer: &mut Buffer) {
+        for op in self.ops.iter() {
+            buffer.edit(&op.removed_range(), &op.inserted);
+        }
+        buffer.take_journal();
+        buffer.carets = Carets::from_positions(&self.carets_after, buffer);
+    }
+
+    /// Approximation of the heap memory kept by this transaction
+    fn memory_size(&self) -> usize {
+        let ops: usize = self
+            .ops
+            .iter()
+            .map(|op| std::mem::size_of::<EditOp>() + op.removed.len() + op.inserted.len())
+            .sum();
+        ops + (self.carets_before.len() + self.carets_after.len()) * std::mem::size_of::<(Absolute, Absolute, bool)>()
+    }
+
+    /// A short description of the edit, like `typed "foo"`
+    fn preview(&self) -> String {
+        let removed: String = self.ops.iter().map(|op| op.removed.as_str()).collect();
+        let inserted: String = self.ops.iter().map(|op| op.inserted.as_str()).collect();
+        match (self.kind, removed.is_empty(), inserted.is_empty()) {
+            (EditKind::Insert, true, _) => format!("typed {}", quote(&inserted)),
+            (_, false, true) => format!("deleted {}", quote(&removed)),
+            (_, true, _) => format!("inserted {}", quote(&inserted)),
+            _ => format!("replaced {} with {}", quote(&removed), quote(&inserted)),
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    const MAX_LEN: usize = 30;
+    let mut q: String = s
+        .chars()
+        .take(MAX_LEN)
+        .map(|c| match c {
+            '\n' => '⏎',
+            '\r' => ' ',
+            '\t' => '→',
+            c => c,
+        })
+        .collect();
+    if s.chars().nth(MAX_LEN).is_some() {
+        q.push('…');
+    }
+    format!("\"{}\"", q)
+}
+
+/// A state of the buffer in the undo tree
+#[derive(Debug, Clone)]
+struct Node {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// The child reached by redo: the last one created or visited
+    redo_child: Option<usize>,
+    revision: usize,
+    /// The edit leading from the parent state to this one, `None` for the root.
+    /// Shared, so cloning the history doesn't copy the edited texts
+    transaction: Option<Arc<Transaction>>,
+}
+
+impl Node {
+    fn root(revision: usize) -> Self {
+        Self {
+            parent: None,
+            children: Vec::new(),
+            redo_child: None,
+            revision,
+            transaction: None,
+        }
+    }
+}
+
+/// A state of the undo history, as listed in the history palette
+#[derive(Debug, Clone)]
+pub struct HistoryState {
+    pub id: usize,
+    pub revision: usize,
+    pub time: Option<SystemTime>,
+    pub description: String,
+    pub is_current: bool,
+}
+
+impl HistoryState {
+    pub fn label(&self, now: SystemTime) -> String {
+        let age = match self.time.map(|t| now.duration_since(t).unwrap_or_default().as_secs()) {
+            None => String::new(),
+            Some(s) if s < 60 => format!("{}s ago", s),
+            Some(s) if s < 3600 => format!("{} min ago", s / 60),
+            Some(s) if s < 86400 => format!("{} h ago", s / 3600),
+            Some(s) => format!("{} days ago", s / 86400),
+        };
+        format!(
+            "{}#{} {} {}",
+            if self.is_current { "● " } else { "" },
+            self.revision,
+            self.description,
+            age
+        )
+    }
+}
+
+/// An undo tree: undoing then editing starts a new branch, and the previous one stays reachable.
+/// The nodes are in a persistent vector, so the clone druid makes of the data on each update is cheap.
+#[derive(Debug, Clone)]
+pub struct UndoHistory {
+    nodes: Vector<Option<Node>>,
+    current: usize,
+    memory: usize,
+    memory_limit: usize,
+    next_revision: usize,
+    /// When false, the next transaction always starts a new undo step
+    group_open: bool,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self {
+            nodes: Vector::unit(Some(Node::root(0))),
+            current: 0,
+            memory: 0,
+            memory_limit: DEFAULT_UNDO_MEMORY_LIMIT,
+            next_revision: 1,
+            group_open: false,
+        }
+    }
+}
+
+impl UndoHistory {
+    fn node(&self, id: usize) -> &Node {
+        self.nodes[id].as_ref().expect("Undo node removed")
+    }
+
+    fn node_mut(&mut self, id: usize) -> &mut Node {
+        self.nodes[id].as_mut().expect("Undo node removed")
+    }
+
+    pub fn push(&mut self, mut transaction: Transaction) {
+        // every change of content get a new revision, even when grouped with the previous step
+        let revision = self.next_revision;
+        self.next_revision += 1;
+        let current = self.current;
+        let id = self.nodes.len();
+        let node = self.nodes[current].as_mut().expect("Undo node removed");
+        match &mut node.transaction {
+            Some(last) if self.group_open && node.children.is_empty() && last.can_group_with(&transaction) => {
+                let last = Arc::make_mut(last);
+                self.memory -= last.memory_size();
+                last.ops.append(&mut transaction.ops);
+                last.carets_after = transaction.carets_after;
+                last.time = transaction.time;
+                self.memory += last.memory_size();
+                node.revision = revision;
+            }
+            _ => {
+                self.memory += transaction.memory_size();
+                node.children.push(id);
+                node.redo_child = Some(id);
+                self.nodes.push_back(Some(Node {
+                    parent: Some(current),
+                    children: Vec::new(),
+                    redo_child: None,
+                    revision,
+                    transaction: Some(Arc::new(transaction)),
+                }));
+                self.current = id;
+            }
+        }
+        self.group_open = true;
+        self.shrink();
+    }
+
+    /// Prevent the next transaction from being grouped with the last one
+    pub fn break_group(&mut self) {
+        self.group_open = false;
+    }
+
+    /// Identify the current state of the buffer
+    pub fn revision(&self) -> usize {
+        self.node(self.current).revision
+    }
+
+    pub fn undo(&mut self, buffer: &mut Buffer) -> bool {
+        self.group_open = false;
+        let current = self.current;
+        let node = self.node(current);
+        match (node.parent, &node.transaction) {
+            (Some(parent), Some(t)) => {
+                t.undo(buffer);
+                self.node_mut(parent).redo_child = Some(current);
+                self.current = parent;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn redo(&mut self, buffer: &mut Buffer) -> bool {
+        self.group_open = false;
+        match self.node(self.current).redo_child {
+            Some(child) => {
+                if let Some(t) = &self.node(child).transaction {
+                    t.redo(buffer);
+                }
+                self.current = child;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Undo and redo until the state `id` is reached, possibly in another branch
+    pub fn goto(&mut self, id: usize, buffer: &mut Buffer) -> bool {
+        if self.nodes.get(id).is_none_or(Option::is_none) {
+            return false;
+        }
+        let path = self.path_to_root(id);
+        while !path.contains(&self.current) {
+            self.undo(buffer);
+        }
+        let common = path.iter().position(|&n| n == self.current).unwrap_or_default();
+        for &n in path[..common].iter().rev() {
+            self.node_mut(self.current).redo_child = Some(n);
+            self.redo(buffer);
+        }
+        true
+    }
+
+    /// Every state of the tree, the most recent first
+    pub fn states(&self) -> Vec<HistoryState> {
+        let mut states: Vec<HistoryState> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(id, n)| n.as_ref().map(|n| (id, n)))
+            .map(|(id, n)| HistoryState {
+                id,
+                revision: n.revision,
+                time: n.transaction.as_ref().map(|t| t.time),
+                description: n
+                    .transaction
+                    .as_ref()
+                    .map_or_else(|| "Oldest state".to_owned(), |t| t.preview()),
+                is_current: id == self.current,
+            })
+            .collect();
+        states.sort_by_key(|s| std::cmp::Reverse(s.revision));
+        states
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    pub fn set_memory_limit(&mut self, limit: usize) {
+        self.memory_limit = limit;
+        self.shrink();
+    }
+
+    /// The node ids from `id` up to the root
+    fn path_to_root(&self, id: usize) -> Vec<usize> {
+        let mut path = vec![id];
+        let mut id = id;
+        while let Some(parent) = self.node(id).parent {
+            path.push(parent);
+            id = parent;
+        }
+        path
+    }
+
+    /// Drop the oldest states until the history fits in the memory limit.
+    /// Branches not leading to the current state are dropped leaf by leaf,
+    /// the current branch from the root. The last transaction is always kept, so the latest edit can be undone.
+    fn shrink(&mut self) {
+        while self.memory > self.memory_limit {
+            let path = self.path_to_root(self.current);
+            let oldest_leaf = self
+                .nodes
+                .iter()
+                .enumerate()
+                .filter_map(|(id, n)| n.as_ref().map(|n| (id, n)))
+                .filter(|(id, n)| n.children.is_empty() && !path.contains(id))
+                .min_by_key(|(_, n)| n.revision)
+                .map(|(id, _)| id);
+            let first_edit = if path.len() > 2 {
+                Some(path[path.len() - 2])
+            } else {
+                None
+            };
+            match (oldest_leaf, first_edit) {
+                (Some(leaf), Some(first)) if self.node(first).revision < self.node(leaf).revision => {
+                    self.make_root(first)
+                }
+                (Some(leaf), _) => self.remove_leaf(leaf),
+                (None, Some(first)) => self.make_root(first),
+                (None, None) => break,
+            }
+        }
+    }
+
+    fn remove_leaf(&mut self, id: usize) {
+        if let Some(node) = self.nodes[id].take() {
+            if let Some(t) = &node.transaction {
+                self.memory -= t.memory_size();
+            }
+            if let Some(parent) = node.parent {
+                let parent = self.node_mut(parent);
+                parent.children.retain(|&c| c != id);
+                if parent.redo_child == Some(id) {
+                    parent.redo_child = parent.children.last().copied();
+                }
+            }
+        }
+    }
+
+    /// Make `id`, a child of the root, the new root, dropping the old root and its other branches
+    fn make_root(&mut self, id: usize) {
+        let mut stack: Vec<usize> = self.node(id).parent.into_iter().collect();
+        while let Some(n) = stack.pop() {
+            if n == id {
+                continue;
+            }
+            if let Some(node) = self.nodes[n].take() {
+                if let Some(t) = &node.transaction {
+                    self.memory -= t.memory_size();
+                }
+                stack.extend(node.children);
+            }
+        }
+        let node = self.node_mut(id);
+        node.parent = None;
+        if let Some(t) = node.transaction.take() {
+            self.memory -= t.memory_size();
+        }
+    }
+
+    /// Load the history saved for `path`, if it was saved for the content in `rope`
+    pub fn load(path: &Path, rope: &Rope) -> Option<Self> {
+        let file = File::open(history_file(path)?).ok()?;
+        Self::read_from(&mut BufReader::new(file), content_hash(rope)).ok()
+    }
+
+    /// Save the history in the cache directory, so it can be restored the next time `path` is opened.
+    /// The least recently saved histories are deleted when the cache gets too large.
+    pub fn save(&self, path: &Path, rope: &Rope) -> io::Result<()> {
+        let history_file =
+            history_file(path).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory"))?;
+        if self.memory > HISTORY_FILE_LIMIT {
+            let _ = fs::remove_file(&history_file);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The undo history is too large",
+            ));
+        }
+        let dir = history_file
+            .parent()
+            .expect("The history file is in the cache directory");
+        fs::create_dir_all(dir)?;
+        // a file being written is never read, even when the file is saved again meanwhile
+        let partial = history_file.with_extension(uuid::Uuid::new_v4().to_string());
+        let mut w = BufWriter::new(File::create(&partial)?);
+        self.write_to(&mut w, content_hash(rope))?;
+        w.flush()?;
+        drop(w);
+        fs::rename(&partial, &history_file)?;
+        prune_history_files(dir)
+    }
+
+    /// Delete the history saved for `path`
+    pub fn forget(path: &Path) -> io::Result<()> {
+        match history_file(path) {
+            Some(history_file) if history_file.exists() => fs::remove_file(history_file),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_to(&self, w: &mut impl Write, content_hash: u64) -> io::Result<()> {
+        // ids are compacted, parents always come before their children
+        let mut ids = vec![u64::MAX; self.nodes.len()];
+        for (new, (old, _)) in self.nodes.iter().enumerate().filter(|(_, n)| n.is_some()).enumerate() {
+            ids[old] = new as u64;
+        }
+        let id = |n: Option<usize>| n.map_or(u64::MAX, |n| ids[n]);
+
+        w.write_all(HISTORY_FILE_MAGIC)?;
+        write_u64(w, content_hash)?;
+        write_u64(w, self.nodes.iter().flatten().count() as u64)?;
+        write_u64(w, id(Some(self.current)))?;
+        for node in self.nodes.iter().flatten() {
+            write_u64(w, id(node.parent))?;
+            write_u64(w, id(node.redo_child))?;
+            write_u64(w, node.revision as u64)?;
+            match &node.transaction {
+                None => w.write_all(&[0])?,
+                Some(t) => {
+                    w.write_all(&[1, t.kind.to_u8()])?;
+                    let time = t.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+                    write_u64(w, time.as_millis() as u64)?;
+                    for carets in [&t.carets_before, &t.carets_after] {
+                        write_u64(w, carets.len() as u64)?;
+                        for &(index, selection, is_clone) in carets.iter() {
+                            write_u64(w, index.index as u64)?;
+                            write_u64(w, selection.index as u64)?;
+                            w.write_all(&[is_clone as u8])?;
+                        }
+                    }
+                    write_u64(w, t.ops.len() as u64)?;
+                    for op in t.ops.iter() {
+                        write_u64(w, op.start.index as u64)?;
+                        write_str(w, &op.removed)?;
+                        write_str(w, &op.inserted)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_from(r: &mut impl Read, content_hash: u64) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != HISTORY_FILE_MAGIC || read_u64(r)? != content_hash {
+            return Err(invalid_data());
+        }
+        let len = read_u64(r)? as usize;
+        let current = read_u64(r)? as usize;
+        let mut history = UndoHistory {
+            nodes: Vector::new(),
+            current,
+            next_revision: 0,
+            ..Default::default()
+        };
+        let mut redo_children = Vec::new();
+        for id in 0..len {
+            let parent = match read_u64(r)? {
+                u64::MAX => None,
+                p if (p as usize) < id => Some(p as usize),
+                _ => return Err(invalid_data()),
+            };
+            redo_children.push(read_u64(r)?);
+            let revision = read_u64(r)? as usize;
+            let transaction = match read_u8(r)? {
+                0 => None,
+                _ => {
+                    let kind = EditKind::from_u8(read_u8(r)?);
+                    let time = UNIX_EPOCH + Duration::from_millis(read_u64(r)?);
+                    let mut carets = [Vec::new(), Vec::new()];
+                    for c in carets.iter_mut() {
+                        for _ in 0..read_u64(r)? {
+                            let index = Absolute::from(read_u64(r)? as usize);
+                            let selection = Absolute::from(read_u64(r)? as usize);
+                            c.push((index, selection, read_u8(r)? != 0));
+                        }
+                    }
+                    let [carets_before, carets_after] = carets;
+                    let mut ops = Vec::new();
+                    for _ in 0..read_u64(r)? {
+                        ops.push(EditOp {
+                            start: Absolute::from(read_u64(r)? as usize),
+                            removed: read_str(r)?,
+                            inserted: read_str(r)?,
+                        });
+                    }
+                    Some(Transaction {
+                        ops,
+                        carets_before,
+                        carets_after,
+                        kind,
+                        time,
+                    })
+                }
+            };
+            if parent.is_some() != transaction.is_some() {
+                return Err(invalid_data());
+            }
+            if let Some(t) = &transaction {
+                history.memory += t.memory_size();
+            }
+            if let Some(p) = parent {
+                history.node_mut(p).children.push(id);
+            }
+            history.next_revision = history.next_revision.max(revision + 1);
+            history.nodes.push_back(Some(Node {
+                parent,
+                children: Vec::new(),
+                redo_child: None,
+                revision,
+                transaction: transaction.map(Arc::new),
+            }));
+        }
+        for (id, redo_child) in redo_children.into_iter().enumerate() {
+            let node = history.node_mut(id);
+            node.redo_child = node.children.iter().copied().find(|&c| c as u64 == redo_child);
+        }
+        if history.nodes.iter().flatten().filter(|n| n.parent.is_none()).count() != 1 || current >= len {
+            return Err(invalid_data());
+        }
+        history.shrink();
+        Ok(history)
+    }
+}
+
+/// The file where the history of `path` is saved, in the user cache directory
+fn history_file(path: &Path) -> Option<PathBuf> {
+    let cache_dir = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Caches"))
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    }?;
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let name: String = Sha256::digest(path.to_string_lossy().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Some(cache_dir.join("nonepad").join("undo").join(name))
+}
+
+/// Delete the histories of `dir` not saved for `HISTORY_MAX_AGE`, then the least recently saved ones
+/// beyond `HISTORY_CACHE_LIMIT`
+fn prune_history_files(dir: &Path) -> io::Result<()> {
+    let now = SystemTime::now();
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    files.sort_by_key(|(modified, ..)| std::cmp::Reverse(*modified));
+    let mut size = 0;
+    for (modified, len, path) in files {
+        size += len;
+        let old = now.duration_since(modified).is_ok_and(|age| age > HISTORY_MAX_AGE);
+        if old || size > HISTORY_CACHE_LIMIT {
+            fs::remove_file(path)?;
+            size -= len;
+        }
+    }
+    Ok(())
+}
+
+/// A hash of the text, stable across the builds of the editor, to check a saved history matches the file
+fn content_hash(rope: &Rope) -> u64 {
+    let mut hasher = Sha256::new();
+    for chunk in rope.chunks() {
+        hasher.update(chunk.as_bytes());
+    }
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&hasher.finalize()[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn invalid_data() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Invalid undo history file")
+}
+
+fn write_u64(w: &mut impl Write, v: u64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> io::Result<()> {
+    write_u64(w, s.len() as u64)?;
+    w.write_all(s.as_bytes())
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut b = [0; 1];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+fn read_str(r: &mut impl Read) -> io::Result<String> {
+    let len = read_u64(r)?;
+    let mut s = String::new();
+    r.take(len).read_to_string(&mut s)?;
+    if s.len() as u64 != len {
+        return Err(invalid_data());
+    }
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(buffer: &mut Buffer, history: &mut UndoHistory, range: Range<Absolute>, text: &str) {
+        let before = buffer.carets.clone();
+        buffer.edit(&range, text);
+        let ops = buffer.take_journal();
+        history.push(Transaction::new(EditKind::Other, ops, &before, &buffer.carets));
+    }
+
+    #[test]
+    fn undo_redo_roundtrip() {
+        let mut buffer = Buffer::from_rope("hello world".into(), 4);
+        let mut history = UndoHistory::default();
+        edit(
+            &mut buffer,
+            &mut history,
+            Absolute::from(0)..Absolute::from(5),
+            "goodbye",
+        );
+        edit(&mut buffer, &mut history, Absolute::from(7)..Absolute::from(7), ",");
+        assert_eq!(buffer.rope.to_string(), "goodbye, world");
+
+        assert!(history.undo(&mut buffer));
+        assert!(history.undo(&mut buffer));
+        assert_eq!(buffer.rope.to_string(), "hello world");
+        assert_eq!(history.revision(), 0);
+        assert!(!history.undo(&mut buffer));
+
+        assert!(history.redo(&mut buffer));
+        assert!(history.redo(&mut buffer));
+        assert_eq!(buffer.rope.to_string(), "goodbye, world");
+    }
+
+    #[test]
+    fn memory_limit_drops_oldest() {
+        let mut buffer = Buffer::from_rope("".into(), 4);
+        let mut history = UndoHistory::default();
+        history.set_memory_limit(1);
+        edit(&mut buffer, &mut history, Absolute::from(0)..Absolute::from(0), "a");
+        edit(&mut buffer, &mut history, Absolute::from(1)..Absolute::from(1), "b");
+
+        assert!(history.undo(&mut buffer));
+        assert!(!history.undo(&mut buffer));
+        assert_eq!(buffer.rope.to_string(), "a");
+        assert_ne!(history.revision(), 0);
+    }
+
+    #[test]
+    fn group_typing() {
+        let mut buffer = Buffer::from_rope("".into(), 4);
+        let mut history = UndoHistory::default();
+        for c in ["a", "b", "c"] {
+            let before = buffer.carets.clone();
+            buffer.insert(c, false);
+            let ops = buffer.take_journal();
+            history.push(Transaction::new(EditKind::Insert, ops, &before, &buffer.carets));
+        }
+        let typed = history.revision();
+        history.break_group();
+        let before = buffer.carets.clone();
+        buffer.insert("d", false);
+        let ops = buffer.take_journal();
+        history.push(Transaction::new(EditKind::Insert, ops, &before, &buffer.carets));
+
+        assert!(history.undo(&mut buffer));
+        assert_eq!(buffer.rope.to_string(), "abc");
+        assert_eq!(history.revision(), typed);
+        assert!(history.undo(&mut buffer));
+        assert_eq!(buffer.rope.to_string(), "");
+    }
+
+    #[test]
+    fn clones_share_transactions() {
+        let mut buffer = Buffer::from_rope("".into(), 4);
+        let mut history = UndoHistory::default();
+        let before = buffer.carets.clone();
+        buffer.insert("a", false);
+        let ops = buffer.take_journal();
+        history.push(Transaction::new(EditKind::Insert, ops, &before, &buffer.carets));
+        let copy = history.clone();
+        let transaction = |h: &UndoHistory| h.node(h.current).transaction.clone().unwrap();
+        assert!(Arc::ptr_eq(&transaction(&history), &transaction(&copy)));
+
+        // grouping the next edit copies the shared transaction instead of changing the clone
+        let before = buffer.carets.clone();
+        buffer.insert("b", false);
+        let ops = buffer.take_journal();
+        history.push(Transaction::new(EditKind::Insert, ops, &before, &buffer.carets));
+        assert_eq!(transaction(&history).ops.len(), 2);
+        assert_eq!(transaction(&copy).ops.len(), 1);
+    }
+
+    #[test]
+    fn branches_stay_reachable() {
+        let mut buffer = Buffer::from_rope("x".into(), 4);
+        let mut history = UndoHistory::default();
+        edit(&mut buffer, &mut history, Absolute::from(1)..Absolute::from(1), "a");
+        let first_branch = history.current;
+        history.undo(&mut buffer);
+        edit(&mut buffer, &mut history, Absolute::from(1)..Absolute::from(1), "b");
+        edit(&mut buffer, &mut history, Absolute::from(2)..Absolute::from(2), "c");
+        assert_eq!(buffer.rope.to_string(), "xbc");
+
+        assert!(history.goto(first_branch, &mut buffer));
+        assert_eq!(buffer.rope.to_string(), "xa");
+
+        let mut saved = Vec::new();
+        history.write_to(&mut saved, content_hash(&buffer.rope)).unwrap();
+        let mut restored = UndoHistory::read_from(&mut saved.as_slice(), content_hash(&buffer.rope)).unwrap();
+        assert_eq!(restored.states().len(), 4);
+        assert!(restored.undo(&mut buffer));
+        assert!(restored.redo(&mut buffer));
+        assert_eq!(buffer.rope.to_string(), "xa");
+    }
+
+    #[test]
+    fn prune_old_histories() {
+        let dir = std::env::temp_dir().join(format!("nonepad-undo-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["recent", "old"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        let old = SystemTime::now() - HISTORY_MAX_AGE - Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(dir.join("old"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        prune_history_files(&dir).unwrap();
+        assert!(dir.join("recent").exists());
+        assert!(!dir.join("old").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
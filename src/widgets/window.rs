@@ -13,6 +13,8 @@ use druid::{
 };
 
 pub(super) const RESET_HELD_STATE: Selector<()> = Selector::new("nonepad.all.reste_held_state");
+/// Show a transient message in the status bar, until the next key press
+pub(super) const SHOW_STATUS: Selector<String> = Selector::new("nonepad.window.show_status");
 
 pub struct NPWindow {
     inner: WidgetPod<NPWindowState, Flex<NPWindowState>>,
@@ -20,7 +22,7 @@ pub struct NPWindow {
     //in_palette: bool,
 }
 
-#[derive(Clone, Data, Lens)]
+#[derive(Clone, Data, Lens, Default)]
 pub struct NPWindowState {
     pub editor: EditStack,
     //pub editor2: EditStack,
@@ -30,34 +32,23 @@ pub struct NPWindowState {
     in_palette: bool,
 }
 
-impl Default for NPWindowState {
-    fn default() -> Self {
-        NPWindowState {
-            editor: EditStack::default(),
-            //editor2: EditStack::default(),
-            status: "Untilted".to_owned(),
-            bottom_panel: BottonPanelState::default(),
-            palette_state: PaletteViewState::default(),
-            in_palette: false,
-        }
-    }
-}
-
 impl NPWindowState {
     pub fn new() -> Self {
         Self { ..Default::default() }
     }
 
+    /// A window with an untitled buffer containing `text`
+    pub fn from_text(text: &str) -> Self {
+        Self {
+            editor: EditStack::untitled(text, Default::default()),
+            ..Default::default()
+        }
+    }
+
     pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<NPWindowState> {
         Ok(Self {
             editor: EditStack::from_file(&path)?,
             //editor2: EditStack::default(),
-            status: path
-                .as_ref()
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string(),
             ..Default::default()
         })
     }
@@ -77,7 +68,13 @@ impl Widget<NPWindowState> for NPWindow {
                 ctx.set_handled();
                 return;
             }
+            druid::Event::KeyDown(_) if !data.status.is_empty() => data.status.clear(),
             druid::Event::MouseUp(_) => ctx.submit_command(super::window::RESET_HELD_STATE),
+            druid::Event::Command(cmd) if cmd.is(SHOW_STATUS) => {
+                data.status = cmd.get_unchecked(SHOW_STATUS).clone();
+                ctx.set_handled();
+                return;
+            }
             druid::Event::Command(cmd) if cmd.is(PALETTE_CALLBACK) => {
                 let item = cmd.get_unchecked(PALETTE_CALLBACK);
                 match &item.1 {
@@ -255,6 +252,8 @@ impl NPWindow {
         })
         .with_text_size(12.0);
 
+        let label_status = Label::new(|data: &NPWindowState, _env: &Env| data.status.clone()).with_text_size(12.0);
+
         let label_right = Label::new(|data: &NPWindowState, _env: &Env| {
             format!(
                 "{}    {}    {}    {}    {}",
@@ -279,6 +278,7 @@ impl NPWindow {
                     .with_child(
                         Flex::row()
                             .with_child(label_left.padding(2.0))
+                            .with_child(label_status.padding((16.0, 2.0)))
                             .with_flex_spacer(1.0)
                             .with_child(label_right.padding(2.0))
                             .padding(1.0)